@@ -1,17 +1,40 @@
 use cosmwasm_std::{
-    attr, coin, entry_point, to_binary, Addr, BankMsg, Binary, CosmosMsg, Deps, DepsMut, Env,
-    MessageInfo, Order, Response, StdError, StdResult, Uint128, WasmMsg,
+    attr, coin, entry_point, from_binary, to_binary, Addr, Api, BankMsg, Binary, CosmosMsg,
+    Decimal, Deps, DepsMut, Empty, Env, Fraction, MessageInfo, Order, Response, StdResult, Storage,
+    Uint128, WasmMsg,
 };
-use cw20::Cw20ExecuteMsg;
+use cw2::{set_contract_version, CONTRACT};
+use cw20::{BalanceResponse, Cw20ExecuteMsg, Cw20QueryMsg, Cw20ReceiveMsg, Denom};
+use cw_storage_plus::{Bound, Map};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::error::ContractError;
+use crate::merkle::{decode_hash, verify_proof};
+use crate::nft::{self, key_to_string};
 
 use crate::msg::{
-    ClaimableAmountResponse, ConfigResponse, DepositInfo, ExecuteMsg, InstantiateMsg,
-    InvestorResponse, ListResponse, QueryMsg, UserUpdateData, WalletInfo,
+    AcceptedDenom, AcceptedDenomInfo, AcceptedDenomsResponse, AllowlistResponse,
+    ClaimableAmountResponse, ConfigResponse, Cw20HookMsg, DenomDeposit, DenomSolvency, DepositInfo,
+    ExecuteMsg, InstantiateMsg, InvestorResponse, LegacyMigrateMsg, ListResponse, MigrateMsg,
+    OperatorsResponse, OraclePriceResponse, OracleQueryMsg, OrderBy, Phase, PhaseResponse,
+    PriceSource, QueryMsg, Role, RolesResponse, SolvencyResponse, StatsResponse, UserUpdateData,
+    WalletInfo,
 };
 use crate::state::{
-    Config, DepositData, Status, UserData, CONFIG, DEPOSIT,  RELEASED_INFO,
-    RETURNED_REFUNDED_INFO, STATUS, USER_DATA,
+    Config, DenomRefunds, DepositData, FinalizedSale, State, Stats, UserData, ACCEPTED_DENOMS,
+    CONFIG, DENOM_DEPOSIT, DENOM_REFUNDS, DENOM_TOTAL, DEPOSIT, FINALIZED_SALE, LEGACY_CONFIG,
+    LEGACY_STATUS, NFT_TOKENS, OPERATORS, RELEASED_INFO, RETURNED_REFUNDED_INFO, ROLES, STATE,
+    STATS, SWEEP_DEBT, SWEPT_INFO, USER_DATA, USER_SCHEDULE,
 };
+use crate::vesting::VestingSchedule;
+
+const CONTRACT_NAME: &str = "crates.io:sayve-prefund";
+const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// page size of Distribute and the paginated queries
+pub(crate) const DEFAULT_LIMIT: u32 = 10;
+pub(crate) const MAX_LIMIT: u32 = 30;
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
@@ -19,274 +42,2090 @@ pub fn instantiate(
     _env: Env,
     info: MessageInfo,
     msg: InstantiateMsg,
-) -> StdResult<Response> {
-    CONFIG.save(
-        deps.storage,
-        &Config {
-            token_addr: deps.api.addr_canonicalize(msg.token_addr.as_str())?,
-            stable_denom: msg.stable_denom,
-            start_time: msg.start_time.u128() as u64,
-            lock_time: msg.lock_time.u128() as u64,
-            vesting_time: msg.vesting_time.u128() as u64,
-            admin: if let Some(admin) = msg.admin {
-                deps.api.addr_canonicalize(&admin)?
-            } else {
-                deps.api.addr_canonicalize(info.sender.as_str())?
-            },
-            team_wallet: if let Some(team_wallet) = msg.team_wallet {
-                deps.api.addr_canonicalize(&team_wallet)?
-            } else {
-                deps.api.addr_canonicalize(info.sender.as_str())?
-            },
-
+) -> Result<Response, ContractError> {
+    if msg.deposit_start_time >= msg.deposit_end_time {
+        return Err(ContractError::InvalidDepositWindow {});
+    }
+    if msg.soft_cap > msg.hard_cap {
+        return Err(ContractError::InvalidCaps {});
+    }
+    validate_deposit_limits(msg.min_deposit, msg.max_deposit_per_wallet)?;
+    if let Some(merkle_root) = &msg.merkle_root {
+        decode_hash(merkle_root)?;
+    }
+    let config = Config {
+        token_addr: deps.api.addr_canonicalize(msg.token_addr.as_str())?,
+        stable_denom: msg.stable_denom,
+        stable_token: if let Some(stable_token) = msg.stable_token {
+            Some(deps.api.addr_canonicalize(&stable_token)?)
+        } else {
+            None
         },
-    )?;
+        staking_contract: if let Some(staking_contract) = msg.staking_contract {
+            Some(deps.api.addr_canonicalize(&staking_contract)?)
+        } else {
+            None
+        },
+        start_time: msg.start_time.u128() as u64,
+        lock_time: msg.lock_time.u128() as u64,
+        vesting_time: msg.vesting_time.u128() as u64,
+        tge_unlock_bps: msg.tge_unlock_bps,
+        schedule_type: msg.schedule_type.unwrap_or_default(),
+        deposit_start_time: msg.deposit_start_time.u128() as u64,
+        deposit_end_time: msg.deposit_end_time.u128() as u64,
+        hard_cap: msg.hard_cap,
+        soft_cap: msg.soft_cap,
+        min_deposit: msg.min_deposit,
+        max_deposit_per_wallet: msg.max_deposit_per_wallet,
+        merkle_root: msg.merkle_root,
+        position_transfers_enabled: true,
+        position_nft: msg.position_nft,
+        pending_admin: None,
+        admin: if let Some(admin) = msg.admin {
+            deps.api.addr_canonicalize(&admin)?
+        } else {
+            deps.api.addr_canonicalize(info.sender.as_str())?
+        },
+        team_wallet: if let Some(team_wallet) = msg.team_wallet {
+            deps.api.addr_canonicalize(&team_wallet)?
+        } else {
+            deps.api.addr_canonicalize(info.sender.as_str())?
+        },
+    };
+    config.vesting_schedule().validate()?;
+    CONFIG.save(deps.storage, &config)?;
+    STATS.save(deps.storage, &Stats::default())?;
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    let admin = deps.api.addr_humanize(&config.admin)?;
+    grant_admin_roles(deps.storage, &admin)?;
 
-    STATUS.save(
+    STATE.save(
         deps.storage,
-        &Status {
-            can_deposit: false,
-            can_withdraw: false,
+        &State {
+            claim_start_time: None,
+            total_deposited: Uint128::zero(),
+            paused: false,
+            pause_reason: None,
         },
     )?;
     Ok(Response::new().add_attributes(vec![attr("action", "instantiate")]))
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> StdResult<Response> {
+pub fn execute(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: ExecuteMsg,
+) -> Result<Response, ContractError> {
+    // everything that moves funds or positions stops while paused
+    if matches!(
+        msg,
+        ExecuteMsg::Deposit { .. }
+            | ExecuteMsg::Receive(_)
+            | ExecuteMsg::Claim { .. }
+            | ExecuteMsg::ClaimAndStake {}
+            | ExecuteMsg::Distribute { .. }
+            | ExecuteMsg::TransferPosition { .. }
+            | ExecuteMsg::TransferNft { .. }
+            | ExecuteMsg::SendNft { .. }
+    ) {
+        assert_not_paused(deps.storage)?;
+    }
     match msg {
-        ExecuteMsg::Deposit {} => deposit(deps, env, info),
-        ExecuteMsg::CanDeposit(can_deposit) => enable_deposit(deps, env, info, can_deposit),
-        ExecuteMsg::EnableWithdraw {} => enable_withdraw(deps, env, info),
+        ExecuteMsg::Deposit { cap, proof } => deposit_native(deps, env, info, cap, proof),
+        ExecuteMsg::Receive(msg) => receive_cw20(deps, env, info, msg),
+        ExecuteMsg::EnableWithdraw { check_solvency } => {
+            enable_withdraw(deps, env, info, check_solvency.unwrap_or(false))
+        }
         ExecuteMsg::Update(user_data_list) => update(deps, env, info, user_data_list),
+        ExecuteMsg::Finalize {
+            token_price,
+            tokens_for_sale,
+        } => finalize(deps, env, info, token_price, tokens_for_sale),
         ExecuteMsg::UpdateToken(token_addr) => update_token(deps, env, info, token_addr),
-        ExecuteMsg::Claim {} => claim(deps, env, info),
+        ExecuteMsg::ProposeNewAdmin { admin } => propose_new_admin(deps, info, admin),
+        ExecuteMsg::CancelAdminProposal {} => cancel_admin_proposal(deps, info),
+        ExecuteMsg::AcceptAdmin {} => accept_admin(deps, info),
+        ExecuteMsg::UpdateTeamWallet { team_wallet } => update_team_wallet(deps, info, team_wallet),
+        ExecuteMsg::GrantRole { role, address } => grant_role(deps, info, role, address),
+        ExecuteMsg::RevokeRole { role, address } => revoke_role(deps, info, role, address),
+        ExecuteMsg::Pause { reason } => pause(deps, info, reason),
+        ExecuteMsg::Unpause {} => unpause(deps, info),
+        ExecuteMsg::UpdateStakingContract { staking_contract } => {
+            update_staking_contract(deps, env, info, staking_contract)
+        }
+        ExecuteMsg::UpdateDepositLimits {
+            min_deposit,
+            max_deposit_per_wallet,
+        } => update_deposit_limits(deps, env, info, min_deposit, max_deposit_per_wallet),
+        ExecuteMsg::UpdateSchedule {
+            start_time,
+            lock_time,
+            vesting_time,
+        } => update_schedule(deps, env, info, start_time, lock_time, vesting_time),
+        ExecuteMsg::RegisterMerkleRoot { merkle_root } => {
+            register_merkle_root(deps, env, info, merkle_root)
+        }
+        ExecuteMsg::UpdateAcceptedDenom {
+            denom,
+            price_source,
+        } => update_accepted_denom(deps, env, info, denom, price_source),
+        ExecuteMsg::Claim {
+            investor,
+            recipient,
+        } => claim(deps, env, info, investor, recipient, false),
+        ExecuteMsg::ClaimAndStake {} => claim(deps, env, info, None, None, true),
+        ExecuteMsg::TransferPosition { to } => transfer_position(deps, env, info, to),
+        ExecuteMsg::UpdatePositionTransfers { enabled } => {
+            update_position_transfers(deps, info, enabled)
+        }
+        ExecuteMsg::ApproveOperator { operator } => approve_operator(deps, info, operator),
+        ExecuteMsg::TransferNft {
+            recipient,
+            token_id,
+        } => nft::transfer_nft(deps, env, info, recipient, token_id),
+        ExecuteMsg::SendNft {
+            contract,
+            token_id,
+            msg,
+        } => nft::send_nft(deps, env, info, contract, token_id, msg),
+        ExecuteMsg::Approve {
+            spender,
+            token_id,
+            expires,
+        } => nft::approve(deps, env, info, spender, token_id, expires),
+        ExecuteMsg::Revoke { spender, token_id } => nft::revoke(deps, env, info, spender, token_id),
+        ExecuteMsg::ApproveAll { operator, expires } => {
+            nft::approve_all(deps, env, info, operator, expires)
+        }
+        ExecuteMsg::RevokeAll { operator } => nft::revoke_all(deps, info, operator),
+        ExecuteMsg::RevokeOperator { operator } => revoke_operator(deps, info, operator),
+        ExecuteMsg::Distribute { start_after, limit } => {
+            distribute(deps, env, info, start_after, limit)
+        }
+        ExecuteMsg::MintPositions { start_after, limit } => {
+            mint_positions(deps, info, start_after, limit)
+        }
     }
 }
-fn deposit(deps: DepsMut, env: Env, info: MessageInfo) -> StdResult<Response> {
-    let config = CONFIG.load(deps.storage)?;
-    let status = STATUS.load(deps.storage)?;
-    if !status.can_deposit {
-        return Err(StdError::generic_err("User is not able to deposit"));
+
+/// Works out where the sale is from the deposit window, the soft cap, the claim switch and
+/// the vesting end.
+pub fn current_phase(config: &Config, state: &State, current_time: u64) -> Phase {
+    if current_time < config.deposit_start_time {
+        Phase::NotStarted
+    } else if current_time < config.deposit_end_time {
+        Phase::DepositOpen
+    } else if state.total_deposited < config.soft_cap {
+        Phase::Refunding
+    } else if state.claim_start_time.is_none() {
+        Phase::Allocating
+    } else if current_time < config.vesting_schedule().end_time() {
+        Phase::Claiming
+    } else {
+        Phase::Closed
+    }
+}
+
+// the asset the sale raises in, refunds and team sweeps are paid out in it too
+fn stable_asset(api: &dyn Api, config: &Config) -> StdResult<Denom> {
+    Ok(match &config.stable_token {
+        Some(stable_token) => Denom::Cw20(api.addr_humanize(stable_token)?),
+        None => Denom::Native(config.stable_denom.clone()),
+    })
+}
+
+fn balance_of(deps: Deps, denom: &Denom, address: &Addr) -> StdResult<Uint128> {
+    Ok(match denom {
+        Denom::Native(denom) => deps.querier.query_balance(address, denom)?.amount,
+        Denom::Cw20(contract_addr) => {
+            let res: BalanceResponse = deps.querier.query_wasm_smart(
+                contract_addr,
+                &Cw20QueryMsg::Balance {
+                    address: address.to_string(),
+                },
+            )?;
+            res.balance
+        }
+    })
+}
+
+fn transfer_msg(denom: &Denom, recipient: &str, amount: Uint128) -> StdResult<CosmosMsg> {
+    Ok(match denom {
+        Denom::Native(denom) => CosmosMsg::Bank(BankMsg::Send {
+            to_address: recipient.to_string(),
+            amount: vec![coin(amount.u128(), denom)],
+        }),
+        Denom::Cw20(contract_addr) => CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: contract_addr.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: recipient.to_string(),
+                amount,
+            })?,
+            funds: vec![],
+        }),
+    })
+}
+
+fn denom_key(denom: &Denom) -> String {
+    match denom {
+        Denom::Native(denom) => denom.clone(),
+        Denom::Cw20(contract_addr) => contract_addr.to_string(),
+    }
+}
+
+fn is_accepted(deps: Deps, config: &Config, denom: &Denom) -> StdResult<bool> {
+    Ok(*denom == stable_asset(deps.api, config)?
+        || ACCEPTED_DENOMS.has(deps.storage, &denom_key(denom)))
+}
+
+fn price_source_rate(
+    deps: Deps,
+    price_source: &PriceSource,
+    denom: &Denom,
+) -> Result<Decimal, ContractError> {
+    let rate = match price_source {
+        PriceSource::Fixed { rate } => *rate,
+        PriceSource::Oracle { contract_addr } => {
+            let res: OraclePriceResponse = deps.querier.query_wasm_smart(
+                contract_addr,
+                &OracleQueryMsg::Price {
+                    denom: denom_key(denom),
+                },
+            )?;
+            res.rate
+        }
+    };
+    if rate.is_zero() {
+        return Err(ContractError::NoPrice {
+            denom: denom_key(denom),
+        });
     }
-    if info.funds.len() > 1usize {
-        return Err(StdError::generic_err(
-            "More than one coin is sent; only one asset is supported",
-        ));
+    Ok(rate)
+}
+
+// normalized units paid for one unit of `denom`, the stable asset is the unit itself
+fn denom_rate(deps: Deps, config: &Config, denom: &Denom) -> Result<Decimal, ContractError> {
+    if *denom == stable_asset(deps.api, config)? {
+        return Ok(Decimal::one());
     }
-    let payment = info
-        .funds
+    let accepted = ACCEPTED_DENOMS
+        .may_load(deps.storage, &denom_key(denom))?
+        .ok_or_else(|| ContractError::DenomNotAccepted {
+            denom: denom_key(denom),
+        })?;
+    price_source_rate(deps, &accepted.price_source, denom)
+}
+
+/// Splits `share` (normalized) of the wallet's deposit over the denoms it came in, pro-rata and
+/// rounded down. A wallet without deposits is paid in the stable asset.
+fn denom_shares(
+    deps: Deps,
+    config: &Config,
+    wallet: &Addr,
+    share: Uint128,
+) -> StdResult<Vec<(Denom, Uint128)>> {
+    let deposits: Vec<DenomDeposit> = DENOM_DEPOSIT
+        .prefix(wallet)
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, v)| v))
+        .collect::<StdResult<_>>()?;
+    let total = deposits
+        .iter()
+        .fold(Uint128::zero(), |acc, d| acc + d.normalized);
+    if total.is_zero() {
+        return Ok(vec![(stable_asset(deps.api, config)?, share)]);
+    }
+    Ok(deposits
+        .into_iter()
+        .map(|d| (d.denom, d.amount.multiply_ratio(share, total)))
+        .collect())
+}
+
+fn add_amount(amounts: &mut Vec<(Denom, Uint128)>, denom: &Denom, amount: Uint128) {
+    if amount.is_zero() {
+        return;
+    }
+    match amounts.iter_mut().find(|(d, _)| d == denom) {
+        Some((_, total)) => *total += amount,
+        None => amounts.push((denom.clone(), amount)),
+    }
+}
+
+// e.g. "5000uusd,200cw20_addr", "0" when nothing moves
+fn amounts_to_string(amounts: &[(Denom, Uint128)]) -> String {
+    if amounts.is_empty() {
+        return "0".to_string();
+    }
+    amounts
         .iter()
-        .find(|x| x.denom == config.stable_denom && x.amount > Uint128::zero())
-        .ok_or_else(|| {
-            StdError::generic_err(format!(
-                "No {} assets are provided to deposit",
-                config.stable_denom.clone()
-            ))
+        .map(|(denom, amount)| format!("{}{}", amount, denom_key(denom)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// applies `book` to the refund totals of every denom in `amounts`
+fn book_refunds(
+    storage: &mut dyn Storage,
+    amounts: &[(Denom, Uint128)],
+    book: fn(&mut DenomRefunds, Uint128),
+) -> StdResult<()> {
+    for (denom, amount) in amounts.iter() {
+        if amount.is_zero() {
+            continue;
+        }
+        DENOM_REFUNDS.update(storage, &denom_key(denom), |refunds| -> StdResult<_> {
+            let mut refunds = refunds.unwrap_or(DenomRefunds {
+                denom: denom.clone(),
+                owed: Uint128::zero(),
+                returned: Uint128::zero(),
+            });
+            book(&mut refunds, *amount);
+            Ok(refunds)
         })?;
+    }
+    Ok(())
+}
+
+fn deposit_native(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    cap: Option<Uint128>,
+    proof: Option<Vec<String>>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let payments: Vec<(Denom, Uint128)> = info
+        .funds
+        .iter()
+        .filter(|x| x.amount > Uint128::zero())
+        .map(|x| (Denom::Native(x.denom.clone()), x.amount))
+        .collect();
+    if payments.is_empty() {
+        return Err(ContractError::InvalidFunds {
+            denom: config.stable_denom,
+        });
+    }
+    deposit(deps, env, info.sender, payments, cap, proof)
+}
+
+fn receive_cw20(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    cw20_msg: Cw20ReceiveMsg,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    match from_binary(&cw20_msg.msg)? {
+        Cw20HookMsg::Deposit { cap, proof } => {
+            // info.sender is the token contract, cw20_msg.sender the depositor
+            let denom = Denom::Cw20(info.sender);
+            if !is_accepted(deps.as_ref(), &config, &denom)? {
+                return Err(ContractError::DenomNotAccepted {
+                    denom: denom_key(&denom),
+                });
+            }
+            if cw20_msg.amount.is_zero() {
+                return Err(ContractError::InvalidFunds {
+                    denom: denom_key(&denom),
+                });
+            }
+            let sender = deps.api.addr_validate(&cw20_msg.sender)?;
+            deposit(
+                deps,
+                env,
+                sender,
+                vec![(denom, cw20_msg.amount)],
+                cap,
+                proof,
+            )
+        }
+        Cw20HookMsg::StakeVotingTokens { .. } => Err(ContractError::UnsupportedHook {}),
+    }
+}
 
-    let deposit = DEPOSIT.may_load(deps.storage, &info.sender)?;
+fn deposit(
+    deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    payments: Vec<(Denom, Uint128)>,
+    cap: Option<Uint128>,
+    proof: Option<Vec<String>>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let mut state = STATE.load(deps.storage)?;
+    if current_phase(&config, &state, env.block.time.seconds()) != Phase::DepositOpen {
+        return Err(ContractError::DepositClosed {});
+    }
+    if let Some(merkle_root) = &config.merkle_root {
+        let proof = proof.unwrap_or_default();
+        if !verify_proof(merkle_root, sender.as_str(), cap, &proof)? {
+            return Err(ContractError::NotAllowlisted {});
+        }
+    }
+    let mut rated_payments = vec![];
+    let mut payment_total = Uint128::zero();
+    for (denom, amount) in payments {
+        let rate = denom_rate(deps.as_ref(), &config, &denom)?;
+        payment_total += amount * rate;
+        rated_payments.push((denom, amount, rate));
+    }
+    if payment_total < config.min_deposit {
+        return Err(ContractError::BelowMinDeposit {
+            min_deposit: config.min_deposit,
+        });
+    }
+
+    let deposit = DEPOSIT.may_load(deps.storage, &sender)?;
+
+    // only what still fits under the hard cap and the wallet limit is accepted,
+    // the rest goes straight back
+    let remaining_cap = config.hard_cap.saturating_sub(state.total_deposited);
+    if remaining_cap.is_zero() {
+        return Err(ContractError::HardCapReached {});
+    }
+    let mut remaining = remaining_cap;
+    let wallet_deposited = deposit
+        .as_ref()
+        .map(|d| d.total_deposited)
+        .unwrap_or_default();
+    if let Some(max_deposit) = config.max_deposit_per_wallet {
+        let remaining_wallet = max_deposit.saturating_sub(wallet_deposited);
+        if remaining_wallet.is_zero() {
+            return Err(ContractError::WalletLimitReached {});
+        }
+        remaining = std::cmp::min(remaining, remaining_wallet);
+    }
+    // the cap only counts when it was proven against the allowlist root
+    if let (Some(cap), Some(_)) = (cap, &config.merkle_root) {
+        let remaining_allowed = cap.saturating_sub(wallet_deposited);
+        if remaining_allowed.is_zero() {
+            return Err(ContractError::WalletLimitReached {});
+        }
+        remaining = std::cmp::min(remaining, remaining_allowed);
+    }
+
+    let mut amount = Uint128::zero();
+    let mut excess_amount = Uint128::zero();
+    let mut msgs = vec![];
+    for (denom, payment_amount, rate) in rated_payments {
+        let normalized = payment_amount * rate;
+        // whole units of the denom whose value still fits are kept
+        let accepted = if normalized <= remaining {
+            payment_amount
+        } else {
+            remaining.multiply_ratio(rate.denominator(), rate.numerator())
+        };
+        let accepted_normalized = accepted * rate;
+        remaining -= accepted_normalized;
+        amount += accepted_normalized;
+        excess_amount += normalized - accepted_normalized;
+        if accepted < payment_amount {
+            msgs.push(transfer_msg(
+                &denom,
+                sender.as_str(),
+                payment_amount - accepted,
+            )?);
+        }
+        if accepted.is_zero() {
+            continue;
+        }
+        let key = denom_key(&denom);
+        let add = |d: Option<DenomDeposit>| -> StdResult<DenomDeposit> {
+            let mut d = d.unwrap_or_else(|| DenomDeposit {
+                denom: denom.clone(),
+                amount: Uint128::zero(),
+                normalized: Uint128::zero(),
+            });
+            d.amount += accepted;
+            d.normalized += accepted_normalized;
+            Ok(d)
+        };
+        DENOM_DEPOSIT.update(deps.storage, (&sender, &key), add)?;
+        DENOM_TOTAL.update(deps.storage, &key, add)?;
+    }
+    if amount.is_zero() {
+        return Err(ContractError::DepositTooSmall {});
+    }
+
+    if deposit.is_none() {
+        STATS.update(deps.storage, |mut stats| -> StdResult<_> {
+            stats.depositors += 1;
+            Ok(stats)
+        })?;
+    }
     let deposit_data = if let Some(mut deposit_data) = deposit {
-        deposit_data.total_deposited += payment.amount;
+        deposit_data.total_deposited += amount;
         deposit_data.deposit_history.push(DepositInfo {
             date: env.block.time,
-            amount: payment.amount,
+            amount,
         });
         deposit_data
     } else {
         DepositData {
-            total_deposited: payment.amount,
+            total_deposited: amount,
             deposit_history: vec![DepositInfo {
                 date: env.block.time,
-                amount: payment.amount,
+                amount,
             }],
         }
     };
 
-    DEPOSIT.save(deps.storage, &info.sender, &deposit_data)?;
+    DEPOSIT.save(deps.storage, &sender, &deposit_data)?;
+    state.total_deposited += amount;
+    STATE.save(deps.storage, &state)?;
+
+    let mut attrs = vec![attr("action", "deposit"), attr("amount", amount)];
+    if !msgs.is_empty() {
+        attrs.push(attr("refund_amount", excess_amount));
+    }
+    Ok(Response::new().add_messages(msgs).add_attributes(attrs))
+}
+
+fn enable_withdraw(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    check_solvency: bool,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    assert_role(deps.as_ref(), &info.sender, Role::Operator)?;
+    let mut state = STATE.load(deps.storage)?;
+    let current_time = env.block.time.seconds();
+    if current_phase(&config, &state, current_time) != Phase::Allocating {
+        return Err(ContractError::EnableWithdrawNotOpen {});
+    }
+    if check_solvency {
+        let solvency = query_solvency(deps.as_ref(), env.clone())?;
+        let mut denom_shortfall = vec![];
+        for d in solvency.denoms.iter() {
+            add_amount(&mut denom_shortfall, &d.denom, d.shortfall);
+        }
+        if !solvency.token_shortfall.is_zero() || !denom_shortfall.is_empty() {
+            return Err(ContractError::Insolvent {
+                token_shortfall: solvency.token_shortfall,
+                denom_shortfall,
+            });
+        }
+    }
+    state.claim_start_time = Some(current_time);
+    STATE.save(deps.storage, &state)?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "enable_withdraw"),
+        attr("claim_start_time", current_time.to_string()),
+    ]))
+}
+fn update_token(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    token_addr: String,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.admin {
+        return Err(ContractError::Unauthorized {});
+    }
+    config.token_addr = deps.api.addr_canonicalize(&token_addr)?;
+    CONFIG.save(deps.storage, &config)?;
+    Ok(Response::new().add_attributes(vec![
+        attr("atcion", " update_token"),
+        attr("token_addr", token_addr),
+    ]))
+}
+
+fn propose_new_admin(
+    deps: DepsMut,
+    info: MessageInfo,
+    admin: String,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.admin {
+        return Err(ContractError::Unauthorized {});
+    }
+    config.pending_admin = Some(deps.api.addr_canonicalize(&admin)?);
+    CONFIG.save(deps.storage, &config)?;
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "propose_new_admin"),
+        attr("pending_admin", admin),
+    ]))
+}
+
+fn cancel_admin_proposal(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.admin {
+        return Err(ContractError::Unauthorized {});
+    }
+    if config.pending_admin.take().is_none() {
+        return Err(ContractError::NoPendingAdmin {});
+    }
+    CONFIG.save(deps.storage, &config)?;
+    Ok(Response::new().add_attributes(vec![attr("action", "cancel_admin_proposal")]))
+}
+
+fn accept_admin(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    let sender = deps.api.addr_canonicalize(info.sender.as_str())?;
+    if config.pending_admin.as_ref() != Some(&sender) {
+        return Err(ContractError::Unauthorized {});
+    }
+    let previous_admin = deps.api.addr_humanize(&config.admin)?;
+    config.admin = sender;
+    config.pending_admin = None;
+    CONFIG.save(deps.storage, &config)?;
+    // the roles follow the admin, the previous key may be the reason for the handover
+    revoke_admin_roles(deps.storage, &previous_admin);
+    grant_admin_roles(deps.storage, &info.sender)?;
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "accept_admin"),
+        attr("previous_admin", previous_admin),
+        attr("admin", info.sender),
+    ]))
+}
+
+fn update_team_wallet(
+    deps: DepsMut,
+    info: MessageInfo,
+    team_wallet: String,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.admin {
+        return Err(ContractError::Unauthorized {});
+    }
+    config.team_wallet = deps.api.addr_canonicalize(&team_wallet)?;
+    CONFIG.save(deps.storage, &config)?;
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "update_team_wallet"),
+        attr("team_wallet", team_wallet),
+    ]))
+}
+
+// the admin starts out holding every role
+fn grant_admin_roles(storage: &mut dyn Storage, admin: &Addr) -> StdResult<()> {
+    ROLES.save(storage, (Role::Operator.as_str(), admin), &Empty {})?;
+    ROLES.save(storage, (Role::Pauser.as_str(), admin), &Empty {})
+}
+
+fn revoke_admin_roles(storage: &mut dyn Storage, admin: &Addr) {
+    ROLES.remove(storage, (Role::Operator.as_str(), admin));
+    ROLES.remove(storage, (Role::Pauser.as_str(), admin));
+}
+
+fn assert_role(deps: Deps, sender: &Addr, role: Role) -> Result<(), ContractError> {
+    if !ROLES.has(deps.storage, (role.as_str(), sender)) {
+        return Err(ContractError::Unauthorized {});
+    }
+    Ok(())
+}
+
+fn grant_role(
+    deps: DepsMut,
+    info: MessageInfo,
+    role: Role,
+    address: String,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.admin {
+        return Err(ContractError::Unauthorized {});
+    }
+    let address = deps.api.addr_validate(&address)?;
+    ROLES.save(deps.storage, (role.as_str(), &address), &Empty {})?;
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "grant_role"),
+        attr("role", role.as_str()),
+        attr("address", address),
+    ]))
+}
+
+fn revoke_role(
+    deps: DepsMut,
+    info: MessageInfo,
+    role: Role,
+    address: String,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.admin {
+        return Err(ContractError::Unauthorized {});
+    }
+    let address = deps.api.addr_validate(&address)?;
+    if !ROLES.has(deps.storage, (role.as_str(), &address)) {
+        return Err(ContractError::MissingRole {
+            address: address.to_string(),
+            role: role.as_str().to_string(),
+        });
+    }
+    ROLES.remove(deps.storage, (role.as_str(), &address));
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "revoke_role"),
+        attr("role", role.as_str()),
+        attr("address", address),
+    ]))
+}
+
+fn assert_not_paused(storage: &dyn Storage) -> Result<(), ContractError> {
+    let state = STATE.load(storage)?;
+    if state.paused {
+        return Err(ContractError::Paused {
+            reason: state.pause_reason,
+        });
+    }
+    Ok(())
+}
+
+fn pause(
+    deps: DepsMut,
+    info: MessageInfo,
+    reason: Option<String>,
+) -> Result<Response, ContractError> {
+    assert_role(deps.as_ref(), &info.sender, Role::Pauser)?;
+    let mut state = STATE.load(deps.storage)?;
+    if state.paused {
+        return Err(ContractError::AlreadyPaused {});
+    }
+    state.paused = true;
+    state.pause_reason = reason.clone();
+    STATE.save(deps.storage, &state)?;
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "pause"),
+        attr("reason", reason.unwrap_or_default()),
+    ]))
+}
+
+fn unpause(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    assert_role(deps.as_ref(), &info.sender, Role::Pauser)?;
+    let mut state = STATE.load(deps.storage)?;
+    if !state.paused {
+        return Err(ContractError::NotPaused {});
+    }
+    state.paused = false;
+    state.pause_reason = None;
+    STATE.save(deps.storage, &state)?;
+    Ok(Response::new().add_attributes(vec![attr("action", "unpause")]))
+}
+
+fn update_staking_contract(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    staking_contract: Option<String>,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.admin {
+        return Err(ContractError::Unauthorized {});
+    }
+    config.staking_contract = if let Some(staking_contract) = &staking_contract {
+        Some(deps.api.addr_canonicalize(staking_contract)?)
+    } else {
+        None
+    };
+    CONFIG.save(deps.storage, &config)?;
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "update_staking_contract"),
+        attr(
+            "staking_contract",
+            staking_contract.unwrap_or_else(|| "none".to_string()),
+        ),
+    ]))
+}
+
+fn validate_deposit_limits(
+    min_deposit: Uint128,
+    max_deposit_per_wallet: Option<Uint128>,
+) -> Result<(), ContractError> {
+    if let Some(max_deposit) = max_deposit_per_wallet {
+        if max_deposit < min_deposit {
+            return Err(ContractError::InvalidDepositLimits {});
+        }
+    }
+    Ok(())
+}
+
+fn update_deposit_limits(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    min_deposit: Uint128,
+    max_deposit_per_wallet: Option<Uint128>,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.admin {
+        return Err(ContractError::Unauthorized {});
+    }
+    validate_deposit_limits(min_deposit, max_deposit_per_wallet)?;
+    config.min_deposit = min_deposit;
+    config.max_deposit_per_wallet = max_deposit_per_wallet;
+    CONFIG.save(deps.storage, &config)?;
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "update_deposit_limits"),
+        attr("min_deposit", min_deposit),
+        attr(
+            "max_deposit_per_wallet",
+            max_deposit_per_wallet
+                .map(|v| v.to_string())
+                .unwrap_or_else(|| "none".to_string()),
+        ),
+    ]))
+}
+
+// large enough that any drop in the vested share shows up after rounding
+const REFERENCE_ALLOCATION: u128 = 1_000_000_000_000_000_000_000_000_000_000;
+
+// once claiming has begun a schedule can only move later and never take back vested tokens
+fn assert_schedule_not_earlier(
+    old_schedule: &VestingSchedule,
+    new_schedule: &VestingSchedule,
+    current_time: u64,
+) -> Result<(), ContractError> {
+    if new_schedule.start_time < old_schedule.start_time
+        || new_schedule.cliff_end_time() < old_schedule.cliff_end_time()
+        || new_schedule.end_time() < old_schedule.end_time()
+    {
+        return Err(ContractError::ScheduleMovedEarlier {});
+    }
+    let allocation = Uint128::from(REFERENCE_ALLOCATION);
+    if new_schedule.vested_amount(allocation, current_time)
+        < old_schedule.vested_amount(allocation, current_time)
+    {
+        return Err(ContractError::VestedAmountReduced {});
+    }
+    Ok(())
+}
+
+fn update_schedule(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    start_time: Option<Uint128>,
+    lock_time: Option<Uint128>,
+    vesting_time: Option<Uint128>,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.admin {
+        return Err(ContractError::Unauthorized {});
+    }
+    let state = STATE.load(deps.storage)?;
+    let old_schedule = config.vesting_schedule();
+    if let Some(start_time) = start_time {
+        config.start_time = start_time.u128() as u64;
+    }
+    if let Some(lock_time) = lock_time {
+        config.lock_time = lock_time.u128() as u64;
+    }
+    if let Some(vesting_time) = vesting_time {
+        config.vesting_time = vesting_time.u128() as u64;
+    }
+    let new_schedule = config.vesting_schedule();
+    new_schedule.validate()?;
+    if state.claim_start_time.is_some() {
+        assert_schedule_not_earlier(&old_schedule, &new_schedule, env.block.time.seconds())?;
+    }
+    CONFIG.save(deps.storage, &config)?;
+
+    let mut attrs = vec![attr("action", "update_schedule")];
+    for (name, old, new) in [
+        (
+            "start_time",
+            old_schedule.start_time,
+            new_schedule.start_time,
+        ),
+        ("lock_time", old_schedule.lock_time, new_schedule.lock_time),
+        (
+            "vesting_time",
+            old_schedule.vesting_time,
+            new_schedule.vesting_time,
+        ),
+    ] {
+        attrs.push(attr(format!("old_{}", name), old.to_string()));
+        attrs.push(attr(format!("new_{}", name), new.to_string()));
+    }
+    Ok(Response::new().add_attributes(attrs))
+}
+
+fn register_merkle_root(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    merkle_root: Option<String>,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.admin {
+        return Err(ContractError::Unauthorized {});
+    }
+    if let Some(merkle_root) = &merkle_root {
+        decode_hash(merkle_root)?;
+    }
+    config.merkle_root = merkle_root.clone();
+    CONFIG.save(deps.storage, &config)?;
     Ok(Response::new().add_attributes(vec![
-        attr("action", "deposit"),
-        attr("amount", payment.amount),
+        attr("action", "register_merkle_root"),
+        attr(
+            "merkle_root",
+            merkle_root.unwrap_or_else(|| "none".to_string()),
+        ),
     ]))
 }
 
-fn enable_deposit(
+fn update_accepted_denom(
     deps: DepsMut,
     _env: Env,
     info: MessageInfo,
-    can_deposit: bool,
-) -> StdResult<Response> {
-    let config = CONFIG.load(deps.storage)?;
+    denom: Denom,
+    price_source: Option<PriceSource>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.admin {
+        return Err(ContractError::Unauthorized {});
+    }
+    let denom = match denom {
+        Denom::Cw20(contract_addr) => Denom::Cw20(deps.api.addr_validate(contract_addr.as_str())?),
+        native => native,
+    };
+    if denom == stable_asset(deps.api, &config)? {
+        return Err(ContractError::StableAssetRate {});
+    }
+    let key = denom_key(&denom);
+    let source = match price_source {
+        Some(price_source) => {
+            let source = match &price_source {
+                PriceSource::Fixed { rate } => {
+                    if rate.is_zero() {
+                        return Err(ContractError::ZeroRate {});
+                    }
+                    rate.to_string()
+                }
+                PriceSource::Oracle { contract_addr } => {
+                    deps.api.addr_validate(contract_addr)?.to_string()
+                }
+            };
+            ACCEPTED_DENOMS.save(
+                deps.storage,
+                &key,
+                &AcceptedDenom {
+                    denom,
+                    price_source,
+                },
+            )?;
+            source
+        }
+        None => {
+            ACCEPTED_DENOMS.remove(deps.storage, &key);
+            "none".to_string()
+        }
+    };
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "update_accepted_denom"),
+        attr("denom", key),
+        attr("price_source", source),
+    ]))
+}
+
+fn update(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    user_data_list: Vec<UserUpdateData>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    assert_role(deps.as_ref(), &info.sender, Role::Operator)?;
+    let state = STATE.load(deps.storage)?;
+    match current_phase(&config, &state, env.block.time.seconds()) {
+        Phase::NotStarted | Phase::DepositOpen => return Err(ContractError::AllocationNotOpen {}),
+        Phase::Refunding => return Err(ContractError::SoftCapMissed {}),
+        _ => {}
+    }
+    if FINALIZED_SALE.may_load(deps.storage)?.is_some() {
+        return Err(ContractError::SaleFinalized {});
+    }
+    let mut transfers = vec![];
+    let mut clawbacks = vec![];
+    let mut refunds_added = vec![];
+    let mut refunds_removed = vec![];
+    let mut new_users = vec![];
+    let mut amended_users = vec![];
+    let mut minted_tokens = vec![];
+    let mut stats = STATS.load(deps.storage)?;
+    for user_data in user_data_list.into_iter() {
+        let data = UserData {
+            allocation: user_data.allocation,
+            refunded: user_data.refunded,
+        };
+        let user_addr = deps.api.addr_validate(&user_data.user_addr)?;
+        let old_data = USER_DATA.may_load(deps.storage, &user_addr)?;
+        if old_data.is_some() {
+            amended_users.push(user_addr.to_string());
+        } else {
+            new_users.push(user_addr.to_string());
+        }
+        USER_DATA.save(deps.storage, &user_addr, &data)?;
+        if nft::mint(deps.storage, &config, &user_addr)? {
+            minted_tokens.push(user_addr.to_string());
+        }
+        let old_schedule = user_vesting_schedule(deps.storage, &config, &user_addr)?;
+        let new_schedule = match (user_data.schedule, user_data.clear_schedule) {
+            (Some(_), Some(true)) => return Err(ContractError::ConflictingSchedule {}),
+            (_, Some(true)) => None,
+            (Some(schedule), _) => Some(schedule),
+            // a re-sent batch without schedules keeps the ones already set
+            (None, _) => USER_SCHEDULE.may_load(deps.storage, &user_addr)?,
+        };
+        let effective_schedule = new_schedule
+            .clone()
+            .unwrap_or_else(|| config.vesting_schedule());
+        if effective_schedule != old_schedule {
+            effective_schedule.validate()?;
+            if state.claim_start_time.is_some() {
+                assert_schedule_not_earlier(
+                    &old_schedule,
+                    &effective_schedule,
+                    env.block.time.seconds(),
+                )?;
+            }
+        }
+        match new_schedule {
+            Some(schedule) => USER_SCHEDULE.save(deps.storage, &user_addr, &schedule)?,
+            None => USER_SCHEDULE.remove(deps.storage, &user_addr),
+        }
+
+        // whatever the user does not get back belongs to the team; only the difference to
+        // what was already swept for this user moves
+        let deposited = DEPOSIT
+            .may_load(deps.storage, &user_addr)?
+            .map(|d| d.total_deposited)
+            .unwrap_or_default();
+        let returned = RETURNED_REFUNDED_INFO
+            .may_load(deps.storage, &user_addr)?
+            .unwrap_or_default();
+        let team_amount = deposited.saturating_sub(std::cmp::max(data.refunded, returned));
+        // what the last Update left with the team for this user
+        let old_team_amount = match &old_data {
+            Some(old_data) => deposited.saturating_sub(std::cmp::max(old_data.refunded, returned)),
+            None => Uint128::zero(),
+        };
+        // a refund is owed up to what was already returned, like the team share above
+        let old_owed = match &old_data {
+            Some(old_data) => std::cmp::max(old_data.refunded, returned),
+            None => Uint128::zero(),
+        };
+        let owed = std::cmp::max(data.refunded, returned);
+        if owed != old_owed {
+            let old_shares = denom_shares(deps.as_ref(), &config, &user_addr, old_owed)?;
+            let shares = denom_shares(deps.as_ref(), &config, &user_addr, owed)?;
+            for ((denom, old_share), (_, share)) in old_shares.iter().zip(shares.iter()) {
+                if share > old_share {
+                    add_amount(&mut refunds_added, denom, *share - *old_share);
+                } else {
+                    add_amount(&mut refunds_removed, denom, *old_share - *share);
+                }
+            }
+        }
+        let old_data = old_data.unwrap_or_default();
+        stats.total_allocated = stats.total_allocated + data.allocation - old_data.allocation;
+        stats.total_refunded = stats.total_refunded + data.refunded - old_data.refunded;
+        stats.total_swept = stats.total_swept + team_amount - old_team_amount;
+        for (denom, team_share) in denom_shares(deps.as_ref(), &config, &user_addr, team_amount)? {
+            let key = denom_key(&denom);
+            let swept = SWEPT_INFO
+                .may_load(deps.storage, (&user_addr, &key))?
+                .unwrap_or_default();
+            if team_share == swept {
+                continue;
+            }
+            if team_share > swept {
+                add_amount(&mut transfers, &denom, team_share - swept);
+            } else {
+                add_amount(&mut clawbacks, &denom, swept - team_share);
+            }
+            SWEPT_INFO.save(deps.storage, (&user_addr, &key), &team_share)?;
+        }
+    }
+
+    STATS.save(deps.storage, &stats)?;
+    book_refunds(deps.storage, &refunds_added, |r, amount| r.owed += amount)?;
+    book_refunds(deps.storage, &refunds_removed, |r, amount| {
+        r.owed = r.owed.saturating_sub(amount)
+    })?;
+    for (denom, amount) in clawbacks.iter() {
+        SWEEP_DEBT.update(deps.storage, &denom_key(denom), |debt| -> StdResult<_> {
+            Ok(debt.unwrap_or_default() + *amount)
+        })?;
+    }
+    let team_wallet = deps.api.addr_humanize(&config.team_wallet)?;
+    let mut sent = vec![];
+    let mut msgs = vec![];
+    for (denom, amount) in transfers {
+        let key = denom_key(&denom);
+        let sweep_debt = SWEEP_DEBT.may_load(deps.storage, &key)?.unwrap_or_default();
+        if amount > sweep_debt {
+            SWEEP_DEBT.remove(deps.storage, &key);
+            msgs.push(transfer_msg(
+                &denom,
+                team_wallet.as_str(),
+                amount - sweep_debt,
+            )?);
+            add_amount(&mut sent, &denom, amount - sweep_debt);
+        } else {
+            SWEEP_DEBT.save(deps.storage, &key, &(sweep_debt - amount))?;
+        }
+    }
+    let mut attrs = vec![
+        attr("action", "update"),
+        attr("transfer_amount", amounts_to_string(&sent)),
+        attr("clawback_amount", amounts_to_string(&clawbacks)),
+        attr("new_users", new_users.join(",")),
+        attr("amended_users", amended_users.join(",")),
+    ];
+    if config.position_nft.is_some() {
+        attrs.push(attr("minted_tokens", minted_tokens.join(",")));
+    }
+    Ok(Response::new().add_messages(msgs).add_attributes(attrs))
+}
+
+fn finalize(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    token_price: Decimal,
+    tokens_for_sale: Uint128,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    assert_role(deps.as_ref(), &info.sender, Role::Operator)?;
+    let state = STATE.load(deps.storage)?;
+    if current_phase(&config, &state, env.block.time.seconds()) != Phase::Allocating {
+        return Err(ContractError::FinalizeNotOpen {});
+    }
+    if FINALIZED_SALE.may_load(deps.storage)?.is_some() {
+        return Err(ContractError::AlreadyFinalized {});
+    }
+    if USER_DATA
+        .keys(deps.storage, None, None, Order::Ascending)
+        .next()
+        .is_some()
+    {
+        return Err(ContractError::AllocationsUploaded {});
+    }
+    if token_price.is_zero() || tokens_for_sale.is_zero() {
+        return Err(ContractError::InvalidSalePrice {});
+    }
+
+    // when oversubscribed only the value of the tokens on offer is kept, pro-rata
+    let accepted_amount = std::cmp::min(state.total_deposited, tokens_for_sale * token_price);
+    let sale = FinalizedSale {
+        token_price,
+        tokens_for_sale,
+        total_deposited: state.total_deposited,
+        accepted_amount,
+    };
+    FINALIZED_SALE.save(deps.storage, &sale)?;
+    STATS.update(deps.storage, |stats| -> StdResult<_> {
+        Ok(finalized_stats(&sale, stats))
+    })?;
+    // the same share of every denom goes to the team, what stays behind is owed as refunds
+    let mut transfers = vec![];
+    let mut refunds = vec![];
+    for item in DENOM_TOTAL.range(deps.storage, None, None, Order::Ascending) {
+        let (_, total) = item?;
+        let team_amount = total
+            .amount
+            .multiply_ratio(accepted_amount, state.total_deposited);
+        add_amount(&mut transfers, &total.denom, team_amount);
+        add_amount(&mut refunds, &total.denom, total.amount - team_amount);
+    }
+    book_refunds(deps.storage, &refunds, |r, amount| r.owed += amount)?;
+    let team_wallet = deps.api.addr_humanize(&config.team_wallet)?;
+    let mut msgs = vec![];
+    for (denom, amount) in transfers.iter() {
+        msgs.push(transfer_msg(denom, team_wallet.as_str(), *amount)?);
+    }
+    // every depositor holds a position now; the tokens are minted by MintPositions or on
+    // their first cw721 use
+    Ok(Response::new().add_messages(msgs).add_attributes(vec![
+        attr("action", "finalize"),
+        attr("token_price", token_price.to_string()),
+        attr("tokens_for_sale", tokens_for_sale),
+        attr("total_deposited", state.total_deposited),
+        attr("transfer_amount", amounts_to_string(&transfers)),
+    ]))
+}
+
+fn mint_positions(
+    deps: DepsMut,
+    info: MessageInfo,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    assert_role(deps.as_ref(), &info.sender, Role::Operator)?;
+    if config.position_nft.is_none() {
+        return Err(ContractError::NftDisabled {});
+    }
+    if FINALIZED_SALE.may_load(deps.storage)?.is_none() {
+        return Err(ContractError::NotFinalized {});
+    }
+
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = match start_after {
+        Some(start_after) => Some(Bound::exclusive(
+            deps.api.addr_validate(&start_after)?.as_bytes(),
+        )),
+        None => None,
+    };
+    let depositors: Vec<Addr> = DEPOSIT
+        .keys(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|k| key_to_string(k).map(Addr::unchecked))
+        .collect::<StdResult<_>>()?;
+    let mut minted_tokens = 0u64;
+    for depositor in depositors.iter() {
+        if nft::mint(deps.storage, &config, depositor)? {
+            minted_tokens += 1;
+        }
+    }
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "mint_positions"),
+        attr("users", depositors.len().to_string()),
+        attr("minted_tokens", minted_tokens.to_string()),
+        // start_after of the next page, empty once the walk is done
+        attr(
+            "last_user",
+            match depositors.last() {
+                Some(last) if depositors.len() == limit => last.to_string(),
+                _ => String::new(),
+            },
+        ),
+    ]))
+}
+
+// totals of a finalized sale before the per-wallet rounding down in finalized_user_data
+fn finalized_stats(sale: &FinalizedSale, stats: Stats) -> Stats {
+    Stats {
+        total_allocated: std::cmp::min(
+            sale.total_deposited
+                .multiply_ratio(sale.token_price.denominator(), sale.token_price.numerator()),
+            sale.tokens_for_sale,
+        ),
+        total_refunded: sale.total_deposited - sale.accepted_amount,
+        total_swept: sale.accepted_amount,
+        ..stats
+    }
+}
+
+// a depositor's share of a finalized sale; both parts round down so the contract never owes
+// more tokens than tokens_for_sale or more stable than it kept back
+fn finalized_user_data(sale: &FinalizedSale, deposited: Uint128) -> UserData {
+    let refunded = deposited.multiply_ratio(
+        sale.total_deposited - sale.accepted_amount,
+        sale.total_deposited,
+    );
+    let allocation = std::cmp::min(
+        deposited.multiply_ratio(sale.token_price.denominator(), sale.token_price.numerator()),
+        deposited.multiply_ratio(sale.tokens_for_sale, sale.total_deposited),
+    );
+    UserData {
+        allocation,
+        refunded,
+    }
+}
+
+// USER_DATA entry of the wallet, worked out from its deposit when the sale was finalized
+// on-chain and the wallet has not claimed yet
+pub(crate) fn may_load_user_data(
+    storage: &dyn Storage,
+    wallet: &Addr,
+) -> StdResult<Option<UserData>> {
+    if let Some(user_data) = USER_DATA.may_load(storage, wallet)? {
+        return Ok(Some(user_data));
+    }
+    let sale = match FINALIZED_SALE.may_load(storage)? {
+        Some(sale) => sale,
+        None => return Ok(None),
+    };
+    Ok(DEPOSIT
+        .may_load(storage, wallet)?
+        .map(|deposit_data| finalized_user_data(&sale, deposit_data.total_deposited)))
+}
+
+fn user_vesting_schedule(
+    storage: &dyn Storage,
+    config: &Config,
+    wallet: &Addr,
+) -> StdResult<VestingSchedule> {
+    Ok(USER_SCHEDULE
+        .may_load(storage, wallet)?
+        .unwrap_or_else(|| config.vesting_schedule()))
+}
+
+fn approve_operator(
+    deps: DepsMut,
+    info: MessageInfo,
+    operator: String,
+) -> Result<Response, ContractError> {
+    let operator = deps.api.addr_validate(&operator)?;
+    if operator == info.sender {
+        return Err(ContractError::SelfOperator {});
+    }
+    OPERATORS.save(deps.storage, (&info.sender, &operator), &Empty {})?;
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "approve_operator"),
+        attr("investor", info.sender),
+        attr("operator", operator),
+    ]))
+}
+
+fn revoke_operator(
+    deps: DepsMut,
+    info: MessageInfo,
+    operator: String,
+) -> Result<Response, ContractError> {
+    let operator = deps.api.addr_validate(&operator)?;
+    if !OPERATORS.has(deps.storage, (&info.sender, &operator)) {
+        return Err(ContractError::OperatorNotApproved {});
+    }
+    OPERATORS.remove(deps.storage, (&info.sender, &operator));
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "revoke_operator"),
+        attr("investor", info.sender),
+        attr("operator", operator),
+    ]))
+}
+
+fn update_position_transfers(
+    deps: DepsMut,
+    info: MessageInfo,
+    enabled: bool,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
     if deps.api.addr_canonicalize(info.sender.as_str())? != config.admin {
-        return Err(StdError::generic_err("Unauthorized"));
+        return Err(ContractError::Unauthorized {});
     }
-    let mut status = STATUS.load(deps.storage)?;
-    status.can_deposit = can_deposit;
-    STATUS.save(deps.storage, &status)?;
+    config.position_transfers_enabled = enabled;
+    CONFIG.save(deps.storage, &config)?;
     Ok(Response::new().add_attributes(vec![
-        attr("action", "enable_deposit"),
-        attr("can_deposit", can_deposit.to_string()),
+        attr("action", "update_position_transfers"),
+        attr("enabled", enabled.to_string()),
     ]))
 }
 
-fn enable_withdraw(deps: DepsMut, _env: Env, info: MessageInfo) -> StdResult<Response> {
-    let config = CONFIG.load(deps.storage)?;
-    if deps.api.addr_canonicalize(info.sender.as_str())? != config.admin {
-        return Err(StdError::generic_err("Unauthorized"));
-    }
-    let mut status = STATUS.load(deps.storage)?;
-    if !status.can_withdraw {
-        status.can_withdraw = true;
-        STATUS.save(deps.storage, &status)?;
+fn move_entry<T: Serialize + DeserializeOwned>(
+    storage: &mut dyn Storage,
+    map: Map<&Addr, T>,
+    from: &Addr,
+    to: &Addr,
+) -> StdResult<()> {
+    if let Some(value) = map.may_load(storage, from)? {
+        map.save(storage, to, &value)?;
+        map.remove(storage, from);
     }
+    Ok(())
+}
 
-    Ok(Response::new().add_attributes(vec![
-        attr("action", "enable_withdraw"),
-        attr("can_withdraw", status.can_withdraw.to_string()),
-    ]))
+fn move_denom_entries<T: Serialize + DeserializeOwned>(
+    storage: &mut dyn Storage,
+    map: Map<(&Addr, &str), T>,
+    from: &Addr,
+    to: &Addr,
+) -> StdResult<()> {
+    let entries: Vec<(Vec<u8>, T)> = map
+        .prefix(from)
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<_>>()?;
+    for (key, value) in entries {
+        let key = key_to_string(key)?;
+        map.save(storage, (to, &key), &value)?;
+        map.remove(storage, (from, &key));
+    }
+    Ok(())
 }
-fn update_token(
+
+/// Moves the whole position of the sender to `to`: deposit, allocation, schedule, what was
+/// released and refunded so far. Operator approvals stay behind.
+fn transfer_position(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
-    token_addr: String,
-) -> StdResult<Response> {
-    let mut config = CONFIG.load(deps.storage)?;
-    if deps.api.addr_canonicalize(info.sender.as_str())? != config.admin {
-        return Err(StdError::generic_err("Unauthorized"));
+    to: String,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if !config.position_transfers_enabled {
+        return Err(ContractError::TransfersDisabled {});
     }
-    config.token_addr = deps.api.addr_canonicalize(&token_addr)?;
-    CONFIG.save(deps.storage, &config)?;
+    if config.position_nft.is_some() {
+        return Err(ContractError::PositionIsNft {});
+    }
+    let state = STATE.load(deps.storage)?;
+    if current_phase(&config, &state, env.block.time.seconds()) == Phase::DepositOpen {
+        return Err(ContractError::TransferDuringDeposit {});
+    }
+    let to = deps.api.addr_validate(&to)?;
+    if to == info.sender {
+        return Err(ContractError::SelfTransfer {});
+    }
+    if !DEPOSIT.has(deps.storage, &info.sender) && !USER_DATA.has(deps.storage, &info.sender) {
+        return Err(ContractError::NoPosition {});
+    }
+    if DEPOSIT.has(deps.storage, &to) || USER_DATA.has(deps.storage, &to) {
+        return Err(ContractError::RecipientHasPosition {});
+    }
+
+    move_entry(deps.storage, DEPOSIT, &info.sender, &to)?;
+    move_entry(deps.storage, USER_DATA, &info.sender, &to)?;
+    move_entry(deps.storage, USER_SCHEDULE, &info.sender, &to)?;
+    move_entry(deps.storage, RELEASED_INFO, &info.sender, &to)?;
+    move_entry(deps.storage, RETURNED_REFUNDED_INFO, &info.sender, &to)?;
+    move_denom_entries(deps.storage, DENOM_DEPOSIT, &info.sender, &to)?;
+    move_denom_entries(deps.storage, SWEPT_INFO, &info.sender, &to)?;
+    let operators: Vec<Addr> = OPERATORS
+        .prefix(&info.sender)
+        .keys(deps.storage, None, None, Order::Ascending)
+        .map(|k| key_to_string(k).map(Addr::unchecked))
+        .collect::<StdResult<_>>()?;
+    for operator in operators.iter() {
+        OPERATORS.remove(deps.storage, (&info.sender, operator));
+    }
+
     Ok(Response::new().add_attributes(vec![
-        attr("atcion", " update_token"),
-        attr("token_addr", token_addr),
+        attr("action", "transfer_position"),
+        attr("from", info.sender),
+        attr("to", to),
     ]))
 }
 
-fn update(
+/// Claims the position of `investor` (the sender by default) and pays `recipient`, by default
+/// the owner of the position: the investor, or the holder of its token in NFT mode. Anyone else
+/// than the owner must be an approved operator. With `stake` the vested tokens go to the
+/// staking contract for the recipient.
+fn claim(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
-    user_data_list: Vec<UserUpdateData>,
-) -> StdResult<Response> {
+    investor: Option<String>,
+    recipient: Option<String>,
+    stake: bool,
+) -> Result<Response, ContractError> {
     let config = CONFIG.load(deps.storage)?;
-    if deps.api.addr_canonicalize(info.sender.as_str())? != config.admin {
-        return Err(StdError::generic_err("Unauthorized"));
-    }
-    let mut transfer_amount = Uint128::zero();
-    for user_data in user_data_list.into_iter() {
-        let data = UserData {
-            allocation: user_data.allocation,
-            refunded: user_data.refunded,
+    let state = STATE.load(deps.storage)?;
+    let action = if stake { "claim_and_stake" } else { "claim" };
+    let staking_contract = match (&config.staking_contract, stake) {
+        (Some(staking_contract), true) => Some(deps.api.addr_humanize(staking_contract)?),
+        (None, true) => return Err(ContractError::StakingNotConfigured {}),
+        _ => None,
+    };
+    let investor = match investor {
+        Some(investor) => deps.api.addr_validate(&investor)?,
+        None => info.sender.clone(),
+    };
+    let owner = nft::position_owner(deps.storage, &config, &investor)?;
+    let by_operator = owner != info.sender && OPERATORS.has(deps.storage, (&owner, &info.sender));
+    if owner != info.sender && !by_operator {
+        let nft_approved = match NFT_TOKENS.may_load(deps.storage, investor.as_str())? {
+            Some(token) => nft::can_send(deps.storage, &env.block, &info.sender, &token)?,
+            None => false,
         };
-        let user_addr = deps.api.addr_validate(&user_data.user_addr)?;
-        USER_DATA.save(deps.storage, &user_addr, &data)?;
-        let deposit_data = DEPOSIT.may_load(deps.storage, &user_addr)?;
-        if let Some(deposit_data) = deposit_data {
-            if deposit_data.total_deposited > data.refunded {
-                transfer_amount += deposit_data.total_deposited - data.refunded;
-            }
+        if !nft_approved {
+            return Err(ContractError::Unauthorized {});
         }
     }
-    let mut msgs = vec![];
-    if transfer_amount > Uint128::zero() {
-        msgs.push(CosmosMsg::Bank(BankMsg::Send {
-            to_address: deps.api.addr_humanize(&config.team_wallet)?.to_string(),
-            amount: vec![coin(transfer_amount.u128(), config.stable_denom)],
-        }));
+    let recipient = match recipient {
+        Some(recipient) => deps.api.addr_validate(&recipient)?,
+        None => owner.clone(),
+    };
+    // an operator claims on the owner's behalf, it can not take custody of the funds
+    if by_operator && recipient != owner {
+        return Err(ContractError::OperatorRecipient {});
+    }
+    let mut extra_attrs = vec![];
+    if investor != info.sender || recipient != investor {
+        extra_attrs.push(attr("investor", investor.as_str()));
+        extra_attrs.push(attr("recipient", recipient.as_str()));
+    }
+    match current_phase(&config, &state, env.block.time.seconds()) {
+        Phase::Claiming | Phase::Closed => {}
+        Phase::Refunding => {
+            return Ok(claim_deposit_refund(deps, &config, &investor, &recipient)?
+                .add_attributes(extra_attrs))
+        }
+        _ => return Err(ContractError::ClaimNotStarted {}),
     }
-    Ok(Response::new().add_messages(msgs).add_attributes(vec![
-        attr("action", "update"),
-        attr("transfer_amount", transfer_amount.to_string()),
-    ]))
-}
 
-fn claim(deps: DepsMut, env: Env, info: MessageInfo) -> StdResult<Response> {
-    let status = STATUS.load(deps.storage)?;
-    if !status.can_withdraw {
-        return Err(StdError::generic_err("do not allow to claim"));
+    let settlement = settle(
+        deps,
+        &config,
+        env.block.time.seconds(),
+        &investor,
+        &recipient,
+        staking_contract.as_ref(),
+    )?;
+    // everything vested so far is already released and no refund is left
+    if settlement.claim_amount.is_zero() && settlement.msgs.is_empty() {
+        return Err(match settlement.next_unlock_time {
+            Some(next_unlock_time) => ContractError::StillLocked {
+                seconds_left: next_unlock_time - env.block.time.seconds(),
+            },
+            None => ContractError::NothingToClaim {},
+        });
     }
-    let config = CONFIG.load(deps.storage)?;
+    if settlement.vested_amount.is_zero() {
+        // claim UST
+        return Ok(Response::new()
+            .add_messages(settlement.msgs)
+            .add_attributes(vec![
+                attr("action", action),
+                attr(
+                    "refunded_amount",
+                    settlement.returned_refunded_amount.to_string(),
+                ),
+            ])
+            .add_attributes(extra_attrs));
+    }
+    Ok(Response::new()
+        .add_messages(settlement.msgs)
+        .add_attributes(vec![
+            attr("action", action),
+            attr("claim_amount", settlement.claim_amount.to_string()),
+            attr(
+                "returned_refunded_amount",
+                settlement.returned_refunded_amount.to_string(),
+            ),
+        ])
+        .add_attributes(extra_attrs))
+}
 
-    let returned_refunded_info = RETURNED_REFUNDED_INFO.may_load(deps.storage, &info.sender)?;
-    let user_data = USER_DATA.load(deps.storage, &info.sender)?;
-    let mut msgs = vec![];
-    let mut return_refunded_amount = Uint128::zero();
+struct Settlement {
+    msgs: Vec<CosmosMsg>,
+    vested_amount: Uint128,
+    claim_amount: Uint128,
+    returned_refunded_amount: Uint128,
+    next_unlock_time: Option<u64>,
+}
 
-    if user_data.refunded > Uint128::zero() && returned_refunded_info.is_none() {
-        msgs.push(CosmosMsg::Bank(BankMsg::Send {
-            to_address: info.sender.to_string(),
-            amount: vec![coin(user_data.refunded.u128(), config.stable_denom)],
-        }));
-        RETURNED_REFUNDED_INFO.save(deps.storage, &info.sender, &user_data.refunded)?;
-        return_refunded_amount = user_data.refunded.clone();
+/// Pays `recipient` the unreturned refund and the vested but unreleased tokens of `wallet`, and
+/// books both. The tokens are staked for the recipient when a staking contract is given.
+fn settle(
+    deps: DepsMut,
+    config: &Config,
+    current_time: u64,
+    wallet: &Addr,
+    recipient: &Addr,
+    staking_contract: Option<&Addr>,
+) -> Result<Settlement, ContractError> {
+    let returned_refunded_amount = RETURNED_REFUNDED_INFO
+        .may_load(deps.storage, wallet)?
+        .unwrap_or_default();
+    let user_data =
+        may_load_user_data(deps.storage, wallet)?.ok_or(ContractError::NoAllocation {})?;
+    if !USER_DATA.has(deps.storage, wallet) {
+        USER_DATA.save(deps.storage, wallet, &user_data)?;
     }
+    let mut msgs = vec![];
+    let mut return_refunded_amount = Uint128::zero();
 
-    let start_time = config.start_time;
-    let lock_time = config.lock_time;
-    let vesting_time = config.vesting_time;
-    let current_time = env.block.time.seconds();
-    if start_time + lock_time > current_time {
-        if msgs.len() > 0 {
-            // claim UST
-            return Ok(Response::new().add_messages(msgs).add_attributes(vec![
-                attr("action", "claim"),
-                attr("refunded_amount", return_refunded_amount.to_string()),
-            ]));
-        } else {
-            return Err(StdError::generic_err("locking funds"));
+    // an amended refund only pays out what was not returned yet, in the denoms deposited
+    if user_data.refunded > returned_refunded_amount {
+        return_refunded_amount = user_data.refunded - returned_refunded_amount;
+        let owed = denom_shares(deps.as_ref(), config, wallet, user_data.refunded)?;
+        let paid = denom_shares(deps.as_ref(), config, wallet, returned_refunded_amount)?;
+        let mut payouts = vec![];
+        for ((denom, owed), (_, paid)) in owed.iter().zip(paid.iter()) {
+            if owed > paid {
+                msgs.push(transfer_msg(denom, recipient.as_str(), *owed - *paid)?);
+                add_amount(&mut payouts, denom, *owed - *paid);
+            }
         }
+        book_refunds(deps.storage, &payouts, |r, amount| r.returned += amount)?;
+        RETURNED_REFUNDED_INFO.save(deps.storage, wallet, &user_data.refunded)?;
+        STATS.update(deps.storage, |mut stats| -> StdResult<_> {
+            stats.total_returned += return_refunded_amount;
+            Ok(stats)
+        })?;
     }
-    let passed_time = current_time - start_time - lock_time;
-
-    let mut claimable_allocation_amount=   if passed_time > vesting_time {
-        user_data.allocation
-    }else{
-        user_data
-            .allocation
-            .multiply_ratio(passed_time, vesting_time)
-    };
-
-    let released_info = RELEASED_INFO.may_load(deps.storage, &info.sender)?;
-    let released_amount = if let Some(released_amount) = released_info {
-        released_amount
-    } else {
-        Uint128::zero()
-    };
 
-    RELEASED_INFO.save(deps.storage, &info.sender, &claimable_allocation_amount)?;
+    let schedule = user_vesting_schedule(deps.storage, config, wallet)?;
+    let vested_amount = schedule.vested_amount(user_data.allocation, current_time);
+    let released_amount = RELEASED_INFO
+        .may_load(deps.storage, wallet)?
+        .unwrap_or_default();
 
-    claimable_allocation_amount -= released_amount;
+    // an allocation corrected below what was already released has nothing left to claim
+    let claimable_allocation_amount = vested_amount.saturating_sub(released_amount);
 
     if claimable_allocation_amount > Uint128::zero() {
+        RELEASED_INFO.save(deps.storage, wallet, &vested_amount)?;
+        STATS.update(deps.storage, |mut stats| -> StdResult<_> {
+            stats.total_released += claimable_allocation_amount;
+            Ok(stats)
+        })?;
+        let msg = match staking_contract {
+            Some(staking_contract) => Cw20ExecuteMsg::Send {
+                contract: staking_contract.to_string(),
+                amount: claimable_allocation_amount,
+                msg: to_binary(&Cw20HookMsg::StakeVotingTokens {
+                    beneficiary: Some(recipient.to_string()),
+                })?,
+            },
+            None => Cw20ExecuteMsg::Transfer {
+                recipient: recipient.to_string(),
+                amount: claimable_allocation_amount,
+            },
+        };
         msgs.push(CosmosMsg::Wasm(WasmMsg::Execute {
             contract_addr: deps.api.addr_humanize(&config.token_addr)?.to_string(),
-            msg: to_binary(&Cw20ExecuteMsg::Transfer {
-                recipient: info.sender.to_string(),
-                amount: claimable_allocation_amount,
-            })?,
+            msg: to_binary(&msg)?,
             funds: vec![],
         }));
     }
+    Ok(Settlement {
+        msgs,
+        vested_amount,
+        claim_amount: claimable_allocation_amount,
+        returned_refunded_amount: return_refunded_amount,
+        next_unlock_time: schedule
+            .next_unlock(user_data.allocation, current_time)
+            .map(|(time, _)| time),
+    })
+}
+
+fn distribute(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    assert_role(deps.as_ref(), &info.sender, Role::Operator)?;
+    let state = STATE.load(deps.storage)?;
+    let current_time = env.block.time.seconds();
+    let refunding = match current_phase(&config, &state, current_time) {
+        Phase::Claiming | Phase::Closed => false,
+        Phase::Refunding => true,
+        _ => return Err(ContractError::ClaimNotStarted {}),
+    };
+
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = match start_after {
+        Some(start_after) => Some(Bound::exclusive(
+            deps.api.addr_validate(&start_after)?.as_bytes(),
+        )),
+        None => None,
+    };
+    let wallets = owed_wallets(deps.storage, refunding, start, limit)?;
+
+    let mut msgs = vec![];
+    let mut claim_amount = Uint128::zero();
+    let mut returned_refunded_amount = Uint128::zero();
+    let mut last_user = String::new();
+    for wallet in wallets.iter() {
+        let owner = nft::position_owner(deps.storage, &config, wallet)?;
+        if refunding {
+            // wallets that already claimed their refund are skipped
+            if !RETURNED_REFUNDED_INFO.has(deps.storage, wallet) {
+                let (refund_msgs, amount) = refund_deposit(deps.branch(), &config, wallet, &owner)?;
+                msgs.extend(refund_msgs);
+                returned_refunded_amount += amount;
+            }
+        } else {
+            let settlement = settle(deps.branch(), &config, current_time, wallet, &owner, None)?;
+            msgs.extend(settlement.msgs);
+            claim_amount += settlement.claim_amount;
+            returned_refunded_amount += settlement.returned_refunded_amount;
+        }
+        last_user = wallet.to_string();
+    }
     Ok(Response::new().add_messages(msgs).add_attributes(vec![
-        attr("action", "claim"),
-        attr("claim_amount", claimable_allocation_amount.to_string()),
+        attr("action", "distribute"),
+        attr("users", wallets.len().to_string()),
+        attr("claim_amount", claim_amount.to_string()),
         attr(
             "returned_refunded_amount",
-            return_refunded_amount.to_string(),
+            returned_refunded_amount.to_string(),
+        ),
+        // start_after of the next page, empty once the walk is done
+        attr(
+            "last_user",
+            if wallets.len() < limit {
+                String::new()
+            } else {
+                last_user
+            },
         ),
     ]))
 }
 
+/// Wallets Claim could pay, in address order. A finalized sale only has USER_DATA for wallets
+/// that claimed and a missed soft cap owes every depositor, so both walk DEPOSIT instead.
+fn owed_wallets(
+    storage: &dyn Storage,
+    refunding: bool,
+    start: Option<Bound>,
+    limit: usize,
+) -> StdResult<Vec<Addr>> {
+    let keys = if refunding || FINALIZED_SALE.may_load(storage)?.is_some() {
+        DEPOSIT.keys(storage, start, None, Order::Ascending)
+    } else {
+        USER_DATA.keys(storage, start, None, Order::Ascending)
+    };
+    keys.take(limit)
+        .map(|k| key_to_string(k).map(Addr::unchecked))
+        .collect()
+}
+
+// pays the whole deposit back once the sale failed to reach its soft cap
+fn claim_deposit_refund(
+    deps: DepsMut,
+    config: &Config,
+    investor: &Addr,
+    recipient: &Addr,
+) -> Result<Response, ContractError> {
+    if RETURNED_REFUNDED_INFO
+        .may_load(deps.storage, investor)?
+        .is_some()
+    {
+        return Err(ContractError::AlreadyRefunded {});
+    }
+    let (msgs, amount) = refund_deposit(deps, config, investor, recipient)?;
+    Ok(Response::new().add_messages(msgs).add_attributes(vec![
+        attr("action", "claim"),
+        attr("claim_amount", "0"),
+        attr("returned_refunded_amount", amount.to_string()),
+    ]))
+}
+
+/// Books the whole deposit of `investor` as returned and builds the transfers to `recipient`,
+/// one per denom deposited.
+fn refund_deposit(
+    deps: DepsMut,
+    config: &Config,
+    investor: &Addr,
+    recipient: &Addr,
+) -> Result<(Vec<CosmosMsg>, Uint128), ContractError> {
+    let deposit_data = DEPOSIT
+        .may_load(deps.storage, investor)?
+        .ok_or(ContractError::NothingToRefund {})?;
+
+    RETURNED_REFUNDED_INFO.save(deps.storage, investor, &deposit_data.total_deposited)?;
+    STATS.update(deps.storage, |mut stats| -> StdResult<_> {
+        stats.total_refunded += deposit_data.total_deposited;
+        stats.total_returned += deposit_data.total_deposited;
+        Ok(stats)
+    })?;
+    let shares = denom_shares(
+        deps.as_ref(),
+        config,
+        investor,
+        deposit_data.total_deposited,
+    )?;
+    book_refunds(deps.storage, &shares, |r, amount| {
+        r.owed += amount;
+        r.returned += amount;
+    })?;
+    let mut msgs = vec![];
+    for (denom, amount) in shares {
+        msgs.push(transfer_msg(&denom, recipient.as_str(), amount)?);
+    }
+    Ok((msgs, deposit_data.total_deposited))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(mut deps: DepsMut, env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
+    let from_version = match CONTRACT.may_load(deps.storage)? {
+        Some(stored) => {
+            if stored.contract != CONTRACT_NAME {
+                return Err(ContractError::WrongContract {
+                    contract: stored.contract,
+                });
+            }
+            if parse_version(&stored.version)? > parse_version(CONTRACT_VERSION)? {
+                return Err(ContractError::Downgrade {
+                    from: stored.version,
+                    to: CONTRACT_VERSION.to_string(),
+                });
+            }
+            stored.version
+        }
+        // deployed before versioning
+        None => {
+            migrate_legacy(deps.branch(), env, msg.legacy)?;
+            "legacy".to_string()
+        }
+    };
+    if STATS.may_load(deps.storage)?.is_none() {
+        let stats = rebuild_stats(deps.storage)?;
+        STATS.save(deps.storage, &stats)?;
+    }
+    if DENOM_REFUNDS
+        .keys(deps.storage, None, None, Order::Ascending)
+        .next()
+        .is_none()
+    {
+        rebuild_denom_refunds(deps.branch())?;
+    }
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "migrate"),
+        attr("from_version", from_version),
+        attr("to_version", CONTRACT_VERSION),
+    ]))
+}
+
+// one full pass over the per-wallet maps for contracts that predate the counters
+fn rebuild_stats(storage: &dyn Storage) -> StdResult<Stats> {
+    let mut stats = Stats::default();
+    for item in DEPOSIT.range(storage, None, None, Order::Ascending) {
+        let (key, deposit_data) = item?;
+        stats.depositors += 1;
+        let wallet = Addr::unchecked(key_to_string(key)?);
+        if let Some(user_data) = USER_DATA.may_load(storage, &wallet)? {
+            let returned = RETURNED_REFUNDED_INFO
+                .may_load(storage, &wallet)?
+                .unwrap_or_default();
+            stats.total_allocated += user_data.allocation;
+            stats.total_refunded += user_data.refunded;
+            stats.total_swept += deposit_data
+                .total_deposited
+                .saturating_sub(std::cmp::max(user_data.refunded, returned));
+        }
+    }
+    for item in RELEASED_INFO.range(storage, None, None, Order::Ascending) {
+        stats.total_released += item?.1;
+    }
+    for item in RETURNED_REFUNDED_INFO.range(storage, None, None, Order::Ascending) {
+        stats.total_returned += item?.1;
+    }
+    Ok(match FINALIZED_SALE.may_load(storage)? {
+        Some(sale) => finalized_stats(&sale, stats),
+        None => stats,
+    })
+}
+
+// one full pass for contracts that predate the per-denom refund totals
+fn rebuild_denom_refunds(deps: DepsMut) -> StdResult<()> {
+    let config = CONFIG.load(deps.storage)?;
+    let mut owed = vec![];
+    let mut returned = vec![];
+    match FINALIZED_SALE.may_load(deps.storage)? {
+        Some(sale) => {
+            for item in DENOM_TOTAL.range(deps.storage, None, None, Order::Ascending) {
+                let (_, total) = item?;
+                let team_amount = total
+                    .amount
+                    .multiply_ratio(sale.accepted_amount, sale.total_deposited);
+                add_amount(&mut owed, &total.denom, total.amount - team_amount);
+            }
+        }
+        None => {
+            for item in USER_DATA.range(deps.storage, None, None, Order::Ascending) {
+                let (key, user_data) = item?;
+                let wallet = Addr::unchecked(key_to_string(key)?);
+                let returned = RETURNED_REFUNDED_INFO
+                    .may_load(deps.storage, &wallet)?
+                    .unwrap_or_default();
+                let amount = std::cmp::max(user_data.refunded, returned);
+                for (denom, share) in denom_shares(deps.as_ref(), &config, &wallet, amount)? {
+                    add_amount(&mut owed, &denom, share);
+                }
+            }
+        }
+    }
+    for item in RETURNED_REFUNDED_INFO.range(deps.storage, None, None, Order::Ascending) {
+        let (key, amount) = item?;
+        let wallet = Addr::unchecked(key_to_string(key)?);
+        for (denom, share) in denom_shares(deps.as_ref(), &config, &wallet, amount)? {
+            add_amount(&mut returned, &denom, share);
+        }
+    }
+    book_refunds(deps.storage, &owed, |r, amount| r.owed += amount)?;
+    book_refunds(deps.storage, &returned, |r, amount| r.returned += amount)
+}
+
+fn parse_version(version: &str) -> Result<semver::Version, ContractError> {
+    version.parse().map_err(|_| ContractError::InvalidVersion {
+        version: version.to_string(),
+    })
+}
+
+/// Rewrites the unversioned layout: the Status switches become the time-based phases and
+/// the config gets the defaults of every later setting. Deposits were stable only, so they
+/// are booked as stable denom deposits, and what the old Update sent to the team is booked
+/// as swept. RELEASED_INFO and RETURNED_REFUNDED_INFO kept their layout and are read as
+/// they are.
+fn migrate_legacy(
+    deps: DepsMut,
+    env: Env,
+    legacy: Option<LegacyMigrateMsg>,
+) -> Result<(), ContractError> {
+    let legacy_config = LEGACY_CONFIG.load(deps.storage)?;
+    let status = LEGACY_STATUS.load(deps.storage)?;
+    let current_time = env.block.time.seconds();
+    let (deposit_start_time, deposit_end_time) = match legacy {
+        Some(legacy) => (
+            legacy.deposit_start_time.map_or(0, |t| t.u128() as u64),
+            legacy.deposit_end_time.u128() as u64,
+        ),
+        None if status.can_deposit => return Err(ContractError::MissingDepositEndTime {}),
+        None => (0, current_time),
+    };
+    if deposit_end_time <= deposit_start_time {
+        return Err(ContractError::InvalidDepositWindow {});
+    }
+    let total_deposited = DEPOSIT
+        .range(deps.storage, None, None, Order::Ascending)
+        .try_fold(Uint128::zero(), |acc, item| -> StdResult<Uint128> {
+            Ok(acc + item?.1.total_deposited)
+        })?;
+
+    let config = Config {
+        admin: legacy_config.admin,
+        pending_admin: None,
+        team_wallet: legacy_config.team_wallet,
+        token_addr: legacy_config.token_addr,
+        stable_denom: legacy_config.stable_denom,
+        stable_token: None,
+        staking_contract: None,
+        start_time: legacy_config.start_time,
+        lock_time: legacy_config.lock_time,
+        vesting_time: legacy_config.vesting_time,
+        tge_unlock_bps: 0,
+        schedule_type: Default::default(),
+        deposit_start_time,
+        deposit_end_time,
+        hard_cap: Uint128::MAX,
+        soft_cap: Uint128::zero(),
+        min_deposit: Uint128::zero(),
+        max_deposit_per_wallet: None,
+        merkle_root: None,
+        position_transfers_enabled: true,
+        position_nft: None,
+    };
+    CONFIG.save(deps.storage, &config)?;
+    STATE.save(
+        deps.storage,
+        &State {
+            claim_start_time: if status.can_withdraw {
+                Some(current_time)
+            } else {
+                None
+            },
+            total_deposited,
+            paused: false,
+            pause_reason: None,
+        },
+    )?;
+    LEGACY_STATUS.remove(deps.storage);
+
+    let stable_denom = Denom::Native(config.stable_denom.clone());
+    let key = denom_key(&stable_denom);
+    let deposits: Vec<(Addr, Uint128)> = DEPOSIT
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| -> StdResult<_> {
+            let (k, deposit_data) = item?;
+            let wallet = key_to_string(k)?;
+            Ok((Addr::unchecked(wallet), deposit_data.total_deposited))
+        })
+        .collect::<StdResult<_>>()?;
+    for (wallet, deposited) in deposits.iter() {
+        let deposit = DenomDeposit {
+            denom: stable_denom.clone(),
+            amount: *deposited,
+            normalized: *deposited,
+        };
+        DENOM_DEPOSIT.save(deps.storage, (wallet, &key), &deposit)?;
+        // the old Update sent everything but the refund to the team
+        if let Some(user_data) = USER_DATA.may_load(deps.storage, wallet)? {
+            let swept = deposited.saturating_sub(user_data.refunded);
+            if !swept.is_zero() {
+                SWEPT_INFO.save(deps.storage, (wallet, &key), &swept)?;
+            }
+        }
+    }
+    if !total_deposited.is_zero() {
+        DENOM_TOTAL.save(
+            deps.storage,
+            &key,
+            &DenomDeposit {
+                denom: stable_denom,
+                amount: total_deposited,
+                normalized: total_deposited,
+            },
+        )?;
+    }
+
+    let admin = deps.api.addr_humanize(&config.admin)?;
+    grant_admin_roles(deps.storage, &admin)?;
+    Ok(())
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> Result<Binary, ContractError> {
     match msg {
         QueryMsg::Config {} => Ok(to_binary(&query_config(deps)?)?),
         QueryMsg::Investor { wallet } => Ok(to_binary(&query_investor(deps, wallet)?)?),
-        QueryMsg::List {} => Ok(to_binary(&query_list(deps)?)?),
+        QueryMsg::List {
+            start_after,
+            limit,
+            order,
+        } => Ok(to_binary(&query_list(deps, start_after, limit, order)?)?),
         QueryMsg::ClaimableAmount { wallet } => {
             Ok(to_binary(&query_claimable_amount(deps, env, wallet)?)?)
         }
+        QueryMsg::Phase {} => Ok(to_binary(&query_phase(deps, env)?)?),
+        QueryMsg::VerifyAllowlist { wallet, cap, proof } => Ok(to_binary(
+            &query_verify_allowlist(deps, wallet, cap, proof)?,
+        )?),
+        QueryMsg::AcceptedDenoms {} => Ok(to_binary(&query_accepted_denoms(deps)?)?),
+        QueryMsg::Operators { wallet } => Ok(to_binary(&query_operators(deps, wallet)?)?),
+        QueryMsg::Roles {} => Ok(to_binary(&query_roles(deps)?)?),
+        QueryMsg::Stats {} => Ok(to_binary(&query_stats(deps)?)?),
+        QueryMsg::Solvency {} => Ok(to_binary(&query_solvency(deps, env)?)?),
+        QueryMsg::OwnerOf {
+            token_id,
+            include_expired,
+        } => Ok(to_binary(&nft::query_owner_of(
+            deps,
+            env,
+            token_id,
+            include_expired,
+        )?)?),
+        QueryMsg::ApprovedForAll {
+            owner,
+            include_expired,
+            start_after,
+            limit,
+        } => Ok(to_binary(&nft::query_approved_for_all(
+            deps,
+            env,
+            owner,
+            include_expired,
+            start_after,
+            limit,
+        )?)?),
+        QueryMsg::NumTokens {} => Ok(to_binary(&nft::query_num_tokens(deps)?)?),
+        QueryMsg::ContractInfo {} => Ok(to_binary(&nft::query_contract_info(deps)?)?),
+        QueryMsg::NftInfo { token_id } => Ok(to_binary(&nft::query_nft_info(deps, token_id)?)?),
+        QueryMsg::AllNftInfo {
+            token_id,
+            include_expired,
+        } => Ok(to_binary(&nft::query_all_nft_info(
+            deps,
+            env,
+            token_id,
+            include_expired,
+        )?)?),
+        QueryMsg::Tokens {
+            owner,
+            start_after,
+            limit,
+        } => Ok(to_binary(&nft::query_tokens(
+            deps,
+            owner,
+            start_after,
+            limit,
+        )?)?),
+        QueryMsg::AllTokens { start_after, limit } => Ok(to_binary(&nft::query_all_tokens(
+            deps,
+            start_after,
+            limit,
+        )?)?),
     }
 }
 
@@ -294,16 +2133,199 @@ fn query_config(deps: Deps) -> StdResult<ConfigResponse> {
     let config: Config = CONFIG.load(deps.storage)?;
     let resp = ConfigResponse {
         admin: deps.api.addr_humanize(&config.admin)?.to_string(),
+        pending_admin: if let Some(pending_admin) = config.pending_admin {
+            Some(deps.api.addr_humanize(&pending_admin)?.to_string())
+        } else {
+            None
+        },
         token_addr: deps.api.addr_humanize(&config.token_addr)?.to_string(),
         stable_denom: config.stable_denom,
+        stable_token: if let Some(stable_token) = config.stable_token {
+            Some(deps.api.addr_humanize(&stable_token)?.to_string())
+        } else {
+            None
+        },
+        staking_contract: if let Some(staking_contract) = config.staking_contract {
+            Some(deps.api.addr_humanize(&staking_contract)?.to_string())
+        } else {
+            None
+        },
         team_wallet: deps.api.addr_humanize(&config.team_wallet)?.to_string(),
         start_time: Uint128::from(config.start_time),
         lock_time: Uint128::from(config.lock_time),
         vesting_time: Uint128::from(config.vesting_time),
+        tge_unlock_bps: config.tge_unlock_bps,
+        schedule_type: config.schedule_type,
+        deposit_start_time: Uint128::from(config.deposit_start_time),
+        deposit_end_time: Uint128::from(config.deposit_end_time),
+        hard_cap: config.hard_cap,
+        soft_cap: config.soft_cap,
+        min_deposit: config.min_deposit,
+        max_deposit_per_wallet: config.max_deposit_per_wallet,
+        merkle_root: config.merkle_root,
+        position_transfers_enabled: config.position_transfers_enabled,
+        position_nft: config.position_nft,
     };
     Ok(resp)
 }
 
+fn query_verify_allowlist(
+    deps: Deps,
+    wallet: String,
+    cap: Option<Uint128>,
+    proof: Vec<String>,
+) -> Result<AllowlistResponse, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let allowed = match &config.merkle_root {
+        Some(merkle_root) => verify_proof(merkle_root, &wallet, cap, &proof)?,
+        None => true,
+    };
+    Ok(AllowlistResponse { allowed })
+}
+
+fn query_accepted_denoms(deps: Deps) -> Result<AcceptedDenomsResponse, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let mut denoms = vec![AcceptedDenomInfo {
+        denom: stable_asset(deps.api, &config)?,
+        price_source: PriceSource::Fixed {
+            rate: Decimal::one(),
+        },
+        rate: Decimal::one(),
+    }];
+    for item in ACCEPTED_DENOMS.range(deps.storage, None, None, Order::Ascending) {
+        let (_, accepted) = item?;
+        let rate = price_source_rate(deps, &accepted.price_source, &accepted.denom)?;
+        denoms.push(AcceptedDenomInfo {
+            denom: accepted.denom,
+            price_source: accepted.price_source,
+            rate,
+        });
+    }
+    Ok(AcceptedDenomsResponse { denoms })
+}
+
+fn query_operators(deps: Deps, wallet: String) -> StdResult<OperatorsResponse> {
+    let wallet = deps.api.addr_validate(&wallet)?;
+    let operators = OPERATORS
+        .prefix(&wallet)
+        .keys(deps.storage, None, None, Order::Ascending)
+        .map(key_to_string)
+        .collect::<StdResult<_>>()?;
+    Ok(OperatorsResponse {
+        wallet: wallet.to_string(),
+        operators,
+    })
+}
+
+fn role_members(deps: Deps, role: Role) -> StdResult<Vec<String>> {
+    ROLES
+        .prefix(role.as_str())
+        .keys(deps.storage, None, None, Order::Ascending)
+        .map(key_to_string)
+        .collect()
+}
+
+fn query_roles(deps: Deps) -> StdResult<RolesResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    Ok(RolesResponse {
+        owner: deps.api.addr_humanize(&config.admin)?.to_string(),
+        operators: role_members(deps, Role::Operator)?,
+        pausers: role_members(deps, Role::Pauser)?,
+    })
+}
+
+fn query_stats(deps: Deps) -> StdResult<StatsResponse> {
+    let state = STATE.load(deps.storage)?;
+    let stats = STATS.load(deps.storage)?;
+    Ok(StatsResponse {
+        total_deposited: state.total_deposited,
+        depositors: stats.depositors,
+        total_allocated: stats.total_allocated,
+        total_refunded: stats.total_refunded,
+        total_returned: stats.total_returned,
+        total_released: stats.total_released,
+        total_swept: stats.total_swept,
+    })
+}
+
+/// Outstanding refunds come from the per-denom totals kept by update, finalize, settle and
+/// refund_deposit, so this reads one entry per denom.
+fn query_solvency(deps: Deps, env: Env) -> StdResult<SolvencyResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    let state = STATE.load(deps.storage)?;
+    let stats = STATS.load(deps.storage)?;
+    let token_balance = balance_of(
+        deps,
+        &Denom::Cw20(deps.api.addr_humanize(&config.token_addr)?),
+        &env.contract.address,
+    )?;
+    let token_liability = stats.total_allocated.saturating_sub(stats.total_released);
+
+    // a missed soft cap owes every deposit back
+    let refunding = current_phase(&config, &state, env.block.time.seconds()) == Phase::Refunding;
+
+    // the stable asset and every deposited denom are reported, owed or not
+    let mut denoms = vec![stable_asset(deps.api, &config)?];
+    for item in DENOM_TOTAL.range(deps.storage, None, None, Order::Ascending) {
+        denoms.push(item?.1.denom);
+    }
+    for item in DENOM_REFUNDS.range(deps.storage, None, None, Order::Ascending) {
+        denoms.push(item?.1.denom);
+    }
+    let mut report: Vec<DenomSolvency> = vec![];
+    for denom in denoms {
+        if report.iter().any(|d| d.denom == denom) {
+            continue;
+        }
+        let key = denom_key(&denom);
+        let refunds = DENOM_REFUNDS.may_load(deps.storage, &key)?;
+        let owed = if refunding {
+            DENOM_TOTAL
+                .may_load(deps.storage, &key)?
+                .map_or(Uint128::zero(), |total| total.amount)
+        } else {
+            refunds.as_ref().map_or(Uint128::zero(), |r| r.owed)
+        };
+        let returned = refunds.map_or(Uint128::zero(), |r| r.returned);
+        let balance = balance_of(deps, &denom, &env.contract.address)?;
+        let refund_liability = owed.saturating_sub(returned);
+        report.push(DenomSolvency {
+            denom,
+            balance,
+            refund_liability,
+            surplus: balance.saturating_sub(refund_liability),
+            shortfall: refund_liability.saturating_sub(balance),
+        });
+    }
+    Ok(SolvencyResponse {
+        token_balance,
+        token_liability,
+        token_surplus: token_balance.saturating_sub(token_liability),
+        token_shortfall: token_liability.saturating_sub(token_balance),
+        denoms: report,
+    })
+}
+
+fn query_phase(deps: Deps, env: Env) -> StdResult<PhaseResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    let state = STATE.load(deps.storage)?;
+    let current_time = env.block.time.seconds();
+    let phase = current_phase(&config, &state, current_time);
+    let time_to_next_phase = match phase {
+        Phase::NotStarted => Some(config.deposit_start_time - current_time),
+        Phase::DepositOpen => Some(config.deposit_end_time - current_time),
+        Phase::Allocating => None,
+        Phase::Claiming => Some(config.vesting_schedule().end_time() - current_time),
+        Phase::Closed | Phase::Refunding => None,
+    };
+    Ok(PhaseResponse {
+        phase,
+        time_to_next_phase,
+        paused: state.paused,
+        pause_reason: state.pause_reason,
+    })
+}
+
 fn query_investor(deps: Deps, wallet: String) -> StdResult<InvestorResponse> {
     let wallet_addr = deps.api.addr_validate(&wallet)?;
     let deposit_data = DEPOSIT.may_load(deps.storage, &wallet_addr)?;
@@ -316,7 +2338,7 @@ fn query_investor(deps: Deps, wallet: String) -> StdResult<InvestorResponse> {
         }
     };
 
-    let user_data = USER_DATA.may_load(deps.storage, &wallet_addr)?;
+    let user_data = may_load_user_data(deps.storage, &wallet_addr)?;
     let user_data = if let Some(user_data) = user_data {
         user_data
     } else {
@@ -328,6 +2350,7 @@ fn query_investor(deps: Deps, wallet: String) -> StdResult<InvestorResponse> {
 
     let refunded_info = RETURNED_REFUNDED_INFO.may_load(deps.storage, &wallet_addr)?;
     let is_refunded = refunded_info.is_some();
+    let config = CONFIG.load(deps.storage)?;
 
     Ok(InvestorResponse {
         total_deposited: deposit_data.total_deposited,
@@ -335,6 +2358,12 @@ fn query_investor(deps: Deps, wallet: String) -> StdResult<InvestorResponse> {
         refunded: user_data.refunded,
         is_refunded,
         deposit_history: deposit_data.deposit_history,
+        deposits: DENOM_DEPOSIT
+            .prefix(&wallet_addr)
+            .range(deps.storage, None, None, Order::Ascending)
+            .map(|item| item.map(|(_, v)| v))
+            .collect::<StdResult<_>>()?,
+        vesting_schedule: user_vesting_schedule(deps.storage, &config, &wallet_addr)?,
     })
 }
 
@@ -344,7 +2373,7 @@ fn query_claimable_amount(
     wallet: String,
 ) -> StdResult<ClaimableAmountResponse> {
     let wallet_addr = Addr::unchecked(wallet);
-    let user_data = USER_DATA.may_load(deps.storage, &wallet_addr)?;
+    let user_data = may_load_user_data(deps.storage, &wallet_addr)?;
     let user_data = if let Some(user_data) = user_data {
         user_data
     } else {
@@ -354,14 +2383,23 @@ fn query_claimable_amount(
         }
     };
     let config = CONFIG.load(deps.storage)?;
-    let status = STATUS.load(deps.storage)?;
+    let state = STATE.load(deps.storage)?;
+    let schedule = user_vesting_schedule(deps.storage, &config, &wallet_addr)?;
+    let current_time = env.block.time.seconds();
+    let (next_unlock_time, next_unlock_amount) =
+        match schedule.next_unlock(user_data.allocation, current_time) {
+            Some((time, amount)) => (Some(time), amount),
+            None => (None, Uint128::zero()),
+        };
 
-    if !status.can_withdraw {
+    if state.claim_start_time.is_none() {
         return Ok(ClaimableAmountResponse {
             lock_time_left: 0,
             tokens_allocated: user_data.allocation.to_string(),
             token_avaiable_to_claim: "0".to_string(),
             returned_ust: "0".to_string(),
+            next_unlock_time,
+            next_unlock_amount,
         });
     }
     let refunded_amount = RETURNED_REFUNDED_INFO.may_load(deps.storage, &wallet_addr)?;
@@ -370,27 +2408,7 @@ fn query_claimable_amount(
     } else {
         Uint128::zero()
     };
-    let lock_time = config.lock_time;
-    let start_time = config.start_time;
-    let vesting_time = config.vesting_time;
-    let current_time = env.block.time.seconds();
-    if start_time + lock_time > current_time {
-        return Ok(ClaimableAmountResponse {
-            lock_time_left: start_time + lock_time - current_time,
-            tokens_allocated: user_data.allocation.to_string(),
-            token_avaiable_to_claim: "0".to_string(),
-            returned_ust: refunded_amount.to_string(),
-        });
-    }
-    let passed_time = current_time - start_time - lock_time;
-
-    let mut claimable_allocation_amount: Uint128 = if passed_time > vesting_time {
-        user_data.allocation
-    }else{
-        user_data
-            .allocation
-            .multiply_ratio(passed_time, vesting_time)
-    };
+    let vested_amount = schedule.vested_amount(user_data.allocation, current_time);
 
     let released_info = RELEASED_INFO.may_load(deps.storage, &wallet_addr)?;
     let released_amount = if let Some(released_amount) = released_info {
@@ -399,23 +2417,46 @@ fn query_claimable_amount(
         Uint128::zero()
     };
 
-    claimable_allocation_amount -= released_amount;
+    let claimable_allocation_amount = vested_amount.saturating_sub(released_amount);
 
     Ok(ClaimableAmountResponse {
-        lock_time_left: 0,
+        lock_time_left: schedule.cliff_end_time().saturating_sub(current_time),
         tokens_allocated: user_data.allocation.to_string(),
         token_avaiable_to_claim: claimable_allocation_amount.to_string(),
         returned_ust: refunded_amount.to_string(),
+        next_unlock_time,
+        next_unlock_amount,
     })
 }
-fn query_list(deps: Deps) -> StdResult<ListResponse> {
+fn query_list(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+    order: Option<OrderBy>,
+) -> StdResult<ListResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start_after = start_after.map(|wallet| Bound::exclusive(wallet.as_str()));
+    let (min, max, order) = match order.unwrap_or(OrderBy::Asc) {
+        OrderBy::Asc => (start_after, None, Order::Ascending),
+        OrderBy::Desc => (None, start_after, Order::Descending),
+    };
     let investors = DEPOSIT
-        .range(deps.storage, None, None, Order::Ascending)
-        .filter_map(|v| v.ok())
-        .map(|(k, v)| WalletInfo {
-            wallet: Addr::unchecked(String::from_utf8(k).unwrap()).to_string(),
-            total: v.total_deposited,
+        .range(deps.storage, min, max, order)
+        .take(limit)
+        .map(|item| {
+            let (key, deposit_data) = item?;
+            let wallet = Addr::unchecked(key_to_string(key)?);
+            let user_data = may_load_user_data(deps.storage, &wallet)?.unwrap_or_default();
+            Ok(WalletInfo {
+                total: deposit_data.total_deposited,
+                allocation: user_data.allocation,
+                refunded: user_data.refunded,
+                released: RELEASED_INFO
+                    .may_load(deps.storage, &wallet)?
+                    .unwrap_or_default(),
+                wallet: wallet.to_string(),
+            })
         })
-        .collect();
+        .collect::<StdResult<_>>()?;
     Ok(ListResponse { investors })
 }
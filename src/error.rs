@@ -0,0 +1,209 @@
+use cosmwasm_std::{StdError, Uint128};
+use cw20::Denom;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ContractError {
+    #[error("{0}")]
+    Std(#[from] StdError),
+
+    #[error("Unauthorized")]
+    Unauthorized {},
+
+    // instantiate and config
+    #[error("deposit_end_time must be later than deposit_start_time")]
+    InvalidDepositWindow {},
+
+    #[error("soft_cap can not exceed hard_cap")]
+    InvalidCaps {},
+
+    #[error("max_deposit_per_wallet can not be lower than min_deposit")]
+    InvalidDepositLimits {},
+
+    #[error("The stable asset is always accepted at a rate of 1")]
+    StableAssetRate {},
+
+    #[error("rate must be greater than zero")]
+    ZeroRate {},
+
+    #[error("No price for {denom}")]
+    NoPrice { denom: String },
+
+    #[error("Denom {denom} is not accepted")]
+    DenomNotAccepted { denom: String },
+
+    #[error("No admin change is pending")]
+    NoPendingAdmin {},
+
+    #[error("{address} does not have the {role} role")]
+    MissingRole { address: String, role: String },
+
+    #[error("tge_unlock_bps can not exceed 10000")]
+    TgeUnlockTooHigh {},
+
+    #[error("Periodic schedule needs a non-zero interval and count")]
+    InvalidPeriodicSchedule {},
+
+    #[error("Custom checkpoints must be in strictly increasing time order")]
+    UnorderedCheckpoints {},
+
+    #[error("Custom checkpoints and the TGE unlock add up to {total_bps} bps instead of 10000")]
+    IncompleteCheckpoints { total_bps: u64 },
+
+    #[error("Invalid merkle hash: {hash}")]
+    InvalidMerkleHash { hash: String },
+
+    #[error("A schedule can not be set and cleared at once")]
+    ConflictingSchedule {},
+
+    #[error("Schedule times can not move earlier once claiming has begun")]
+    ScheduleMovedEarlier {},
+
+    #[error("Schedule change would reduce already vested amounts")]
+    VestedAmountReduced {},
+
+    // pause
+    #[error("Contract is paused{}", .reason.as_ref().map(|r| format!(": {}", r)).unwrap_or_default())]
+    Paused { reason: Option<String> },
+
+    #[error("Contract is already paused")]
+    AlreadyPaused {},
+
+    #[error("Contract is not paused")]
+    NotPaused {},
+
+    // deposits
+    #[error("Deposit window is not open")]
+    DepositClosed {},
+
+    #[error("No {denom} assets are provided to deposit")]
+    InvalidFunds { denom: String },
+
+    #[error("Unsupported CW20 hook message")]
+    UnsupportedHook {},
+
+    #[error("Address is not on the allowlist")]
+    NotAllowlisted {},
+
+    #[error("Deposit is below the minimum of {min_deposit}")]
+    BelowMinDeposit { min_deposit: Uint128 },
+
+    #[error("Hard cap is reached")]
+    HardCapReached {},
+
+    #[error("Wallet deposit limit is reached")]
+    WalletLimitReached {},
+
+    #[error("Deposit is too small to be accepted")]
+    DepositTooSmall {},
+
+    // allocation
+    #[error("Allocations can only be updated after the deposit window closes")]
+    AllocationNotOpen {},
+
+    #[error("Soft cap is not reached; all deposits are refunded")]
+    SoftCapMissed {},
+
+    #[error("Sale is finalized; allocations are computed on-chain")]
+    SaleFinalized {},
+
+    #[error("Sale can only be finalized after the deposit window closes")]
+    FinalizeNotOpen {},
+
+    #[error("Sale is already finalized")]
+    AlreadyFinalized {},
+
+    #[error("Sale is not finalized")]
+    NotFinalized {},
+
+    #[error("Allocations are already uploaded through Update")]
+    AllocationsUploaded {},
+
+    #[error("token_price and tokens_for_sale must be greater than zero")]
+    InvalidSalePrice {},
+
+    #[error("Withdraw can only be enabled after the deposit window closes")]
+    EnableWithdrawNotOpen {},
+
+    #[error(
+        "Contract is short {token_shortfall} tokens and refunds in {} denoms",
+        .denom_shortfall.len()
+    )]
+    Insolvent {
+        token_shortfall: Uint128,
+        denom_shortfall: Vec<(Denom, Uint128)>,
+    },
+
+    // claims
+    #[error("Claiming has not started")]
+    ClaimNotStarted {},
+
+    #[error("Tokens are still locked for {seconds_left} seconds")]
+    StillLocked { seconds_left: u64 },
+
+    #[error("Nothing to claim")]
+    NothingToClaim {},
+
+    #[error("No allocation for this wallet")]
+    NoAllocation {},
+
+    #[error("Staking contract is not configured")]
+    StakingNotConfigured {},
+
+    #[error("Deposit is already refunded")]
+    AlreadyRefunded {},
+
+    #[error("Nothing to refund")]
+    NothingToRefund {},
+
+    // operators and positions
+    #[error("Can not approve yourself as operator")]
+    SelfOperator {},
+
+    #[error("Operator is not approved")]
+    OperatorNotApproved {},
+
+    #[error("Operators can only claim to the investor")]
+    OperatorRecipient {},
+
+    #[error("Position transfers are disabled")]
+    TransfersDisabled {},
+
+    #[error("Positions move with their NFT; use TransferNft")]
+    PositionIsNft {},
+
+    #[error("Positions can not be transferred while deposits are open")]
+    TransferDuringDeposit {},
+
+    #[error("Can not transfer a position to yourself")]
+    SelfTransfer {},
+
+    #[error("No position for this wallet")]
+    NoPosition {},
+
+    #[error("Recipient already has a position")]
+    RecipientHasPosition {},
+
+    // position NFTs
+    #[error("Position NFTs are not enabled")]
+    NftDisabled {},
+
+    #[error("Token {token_id} does not exist")]
+    TokenNotFound { token_id: String },
+
+    #[error("Expiration is already expired")]
+    Expired {},
+
+    // migration
+    #[error("Can not migrate from {contract}")]
+    WrongContract { contract: String },
+
+    #[error("Can not downgrade from {from} to {to}")]
+    Downgrade { from: String, to: String },
+
+    #[error("Invalid contract version: {version}")]
+    InvalidVersion { version: String },
+
+    #[error("deposit_end_time is required while deposits are open")]
+    MissingDepositEndTime {},
+}
@@ -1,6 +1,12 @@
 pub mod contract;
+mod error;
+pub mod merkle;
 pub mod msg;
+pub mod nft;
 pub mod state;
+pub mod vesting;
+
+pub use crate::error::ContractError;
 
 #[cfg(test)]
 mod testing;
@@ -0,0 +1,40 @@
+use cosmwasm_std::Uint128;
+use sha2::Digest;
+
+use crate::error::ContractError;
+
+// leaf = sha256(address) or sha256(address + cap) when the address has its own cap
+fn leaf_hash(address: &str, cap: Option<Uint128>) -> [u8; 32] {
+    let leaf = match cap {
+        Some(cap) => format!("{}{}", address, cap),
+        None => address.to_string(),
+    };
+    sha2::Sha256::digest(leaf.as_bytes()).into()
+}
+
+pub fn decode_hash(hash: &str) -> Result<[u8; 32], ContractError> {
+    let mut buf = [0u8; 32];
+    hex::decode_to_slice(hash, &mut buf).map_err(|_| ContractError::InvalidMerkleHash {
+        hash: hash.to_string(),
+    })?;
+    Ok(buf)
+}
+
+/// Checks that `address` (with its optional cap) is a leaf of the tree with the given root.
+/// Pairs are hashed in sorted order, so the proof does not need to carry left/right flags.
+pub fn verify_proof(
+    merkle_root: &str,
+    address: &str,
+    cap: Option<Uint128>,
+    proof: &[String],
+) -> Result<bool, ContractError> {
+    let root = decode_hash(merkle_root)?;
+    let mut hash = leaf_hash(address, cap);
+    for p in proof {
+        let proof_hash = decode_hash(p)?;
+        let mut hashes = [hash, proof_hash];
+        hashes.sort_unstable();
+        hash = sha2::Sha256::digest(&hashes.concat()).into();
+    }
+    Ok(hash == root)
+}
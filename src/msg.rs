@@ -1,4 +1,6 @@
-use cosmwasm_std::{Timestamp, Uint128};
+use crate::vesting::{ScheduleType, VestingSchedule};
+use cosmwasm_std::{to_binary, Binary, CosmosMsg, Decimal, StdResult, Timestamp, Uint128, WasmMsg};
+use cw20::{Cw20ReceiveMsg, Denom, Expiration};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
@@ -6,11 +8,46 @@ use serde::{Deserialize, Serialize};
 pub struct InstantiateMsg {
     pub token_addr: String,
     pub stable_denom: String,
+    pub stable_token: Option<String>,
+    // receives the tokens of ClaimAndStake
+    pub staking_contract: Option<String>,
     pub admin: Option<String>,
     pub team_wallet: Option<String>,
     pub start_time: Uint128,
     pub lock_time: Uint128,
-    pub vesting_time: Uint128
+    pub vesting_time: Uint128,
+    pub tge_unlock_bps: u64,
+    // defaults to linear
+    pub schedule_type: Option<ScheduleType>,
+    pub deposit_start_time: Uint128,
+    pub deposit_end_time: Uint128,
+    pub hard_cap: Uint128,
+    pub soft_cap: Uint128,
+    pub min_deposit: Uint128,
+    pub max_deposit_per_wallet: Option<Uint128>,
+    pub merkle_root: Option<String>,
+    // mints a CW721 token for every finalized position when set
+    pub position_nft: Option<PositionNftInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrateMsg {
+    // only read when migrating a contract deployed before versioning
+    pub legacy: Option<LegacyMigrateMsg>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LegacyMigrateMsg {
+    // deposit window of the migrated sale; required while can_deposit is still on, without
+    // it the window closes now
+    pub deposit_start_time: Option<Uint128>,
+    pub deposit_end_time: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PositionNftInfo {
+    pub name: String,
+    pub symbol: String,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -18,43 +55,259 @@ pub struct UserUpdateData {
     pub user_addr: String,
     pub allocation: Uint128,
     pub refunded: Uint128,
+    // replaces the schedule in Config for this user, None keeps the current one
+    pub schedule: Option<VestingSchedule>,
+    // drops the user's schedule so Config applies again
+    pub clear_schedule: Option<bool>,
 }
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ExecuteMsg {
-    Deposit {},
-    CanDeposit(bool),
+    Deposit {
+        // per-address cap committed in the allowlist leaf
+        cap: Option<Uint128>,
+        proof: Option<Vec<String>>,
+    },
+    Receive(Cw20ReceiveMsg),
     Update(Vec<UserUpdateData>),
+    Finalize {
+        token_price: Decimal,
+        tokens_for_sale: Uint128,
+    },
     UpdateToken(String),
-    EnableWithdraw {},
-    Claim {},
+    // the proposed admin takes over once it sends AcceptAdmin
+    ProposeNewAdmin {
+        admin: String,
+    },
+    CancelAdminProposal {},
+    AcceptAdmin {},
+    UpdateTeamWallet {
+        team_wallet: String,
+    },
+    // owner only, roles are independent of each other
+    GrantRole {
+        role: Role,
+        address: String,
+    },
+    RevokeRole {
+        role: Role,
+        address: String,
+    },
+    // pauser only
+    Pause {
+        reason: Option<String>,
+    },
+    Unpause {},
+    UpdateStakingContract {
+        staking_contract: Option<String>,
+    },
+    UpdateDepositLimits {
+        min_deposit: Uint128,
+        max_deposit_per_wallet: Option<Uint128>,
+    },
+    // once claiming has begun times can only move later, and only while that
+    // does not take back anything already vested
+    UpdateSchedule {
+        start_time: Option<Uint128>,
+        lock_time: Option<Uint128>,
+        vesting_time: Option<Uint128>,
+    },
+    RegisterMerkleRoot {
+        merkle_root: Option<String>,
+    },
+    // None takes the denom off the whitelist
+    UpdateAcceptedDenom {
+        denom: Denom,
+        price_source: Option<PriceSource>,
+    },
+    // refuses to open claims while the Solvency query reports a shortfall when set
+    EnableWithdraw {
+        check_solvency: Option<bool>,
+    },
+    Claim {
+        // claim for this investor as their approved operator
+        investor: Option<String>,
+        // pay tokens and refund here instead of the investor, not allowed for operators
+        recipient: Option<String>,
+    },
+    // moves the sender's whole position to another wallet
+    TransferPosition {
+        to: String,
+    },
+    UpdatePositionTransfers {
+        enabled: bool,
+    },
+    ApproveOperator {
+        operator: String,
+    },
+    // cw721 interface of the position tokens, the token_id is the original investor address
+    TransferNft {
+        recipient: String,
+        token_id: String,
+    },
+    SendNft {
+        contract: String,
+        token_id: String,
+        msg: Binary,
+    },
+    Approve {
+        spender: String,
+        token_id: String,
+        expires: Option<Expiration>,
+    },
+    Revoke {
+        spender: String,
+        token_id: String,
+    },
+    ApproveAll {
+        operator: String,
+        expires: Option<Expiration>,
+    },
+    RevokeAll {
+        operator: String,
+    },
+    RevokeOperator {
+        operator: String,
+    },
+    // claims and sends the vested tokens to the staking contract for the investor
+    ClaimAndStake {},
+    // admin pushes what Claim would pay to a page of investors
+    Distribute {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    // mints the position tokens of a finalized sale for a page of depositors
+    MintPositions {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
     Config {},
-    List {},
-    ClaimableAmount { wallet: String },
-    Investor { wallet: String },
+    // depositors by wallet address, ascending unless asked otherwise
+    List {
+        start_after: Option<String>,
+        limit: Option<u32>,
+        order: Option<OrderBy>,
+    },
+    ClaimableAmount {
+        wallet: String,
+    },
+    Investor {
+        wallet: String,
+    },
+    Phase {},
+    VerifyAllowlist {
+        wallet: String,
+        cap: Option<Uint128>,
+        proof: Vec<String>,
+    },
+    AcceptedDenoms {},
+    // operators the wallet approved to claim for it
+    Operators {
+        wallet: String,
+    },
+    Roles {},
+    // sale wide totals
+    Stats {},
+    // contract balances against what it still owes investors
+    Solvency {},
+    // cw721 queries of the position tokens
+    OwnerOf {
+        token_id: String,
+        include_expired: Option<bool>,
+    },
+    ApprovedForAll {
+        owner: String,
+        include_expired: Option<bool>,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    NumTokens {},
+    ContractInfo {},
+    NftInfo {
+        token_id: String,
+    },
+    AllNftInfo {
+        token_id: String,
+        include_expired: Option<bool>,
+    },
+    Tokens {
+        owner: String,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    AllTokens {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum Cw20HookMsg {
-    StakeVotingTokens {},
+    Deposit {
+        cap: Option<Uint128>,
+        proof: Option<Vec<String>>,
+    },
+    // hook of the staking contract, stakes for beneficiary instead of the sender when set
+    StakeVotingTokens {
+        beneficiary: Option<String>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceSource {
+    // normalized units paid for one unit of the denom
+    Fixed { rate: Decimal },
+    // contract answering OracleQueryMsg::Price with the same rate
+    Oracle { contract_addr: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AcceptedDenom {
+    pub denom: Denom,
+    pub price_source: PriceSource,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OracleQueryMsg {
+    Price { denom: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OraclePriceResponse {
+    pub rate: Decimal,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct ConfigResponse {
     pub admin: String,
+    pub pending_admin: Option<String>,
     pub token_addr: String,
     pub team_wallet: String,
     pub stable_denom: String,
+    pub stable_token: Option<String>,
+    pub staking_contract: Option<String>,
     pub start_time: Uint128,
     pub lock_time: Uint128,
     pub vesting_time: Uint128,
-
+    pub tge_unlock_bps: u64,
+    pub schedule_type: ScheduleType,
+    pub deposit_start_time: Uint128,
+    pub deposit_end_time: Uint128,
+    pub hard_cap: Uint128,
+    pub soft_cap: Uint128,
+    pub min_deposit: Uint128,
+    pub max_deposit_per_wallet: Option<Uint128>,
+    pub merkle_root: Option<String>,
+    pub position_transfers_enabled: bool,
+    pub position_nft: Option<PositionNftInfo>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -64,6 +317,8 @@ pub struct InvestorResponse {
     pub refunded: Uint128,
     pub is_refunded: bool,
     pub deposit_history: Vec<DepositInfo>,
+    pub deposits: Vec<DenomDeposit>,
+    pub vesting_schedule: VestingSchedule,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -71,20 +326,220 @@ pub struct DepositInfo {
     pub date: Timestamp,
     pub amount: Uint128,
 }
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DenomDeposit {
+    pub denom: Denom,
+    // raw amount of the denom
+    pub amount: Uint128,
+    // value of `amount` at the rate it was deposited at
+    pub normalized: Uint128,
+}
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct ClaimableAmountResponse {
     pub lock_time_left: u64, //in seconds
     pub tokens_allocated: String,
     pub token_avaiable_to_claim: String,
     pub returned_ust: String,
+    pub next_unlock_time: Option<u64>,
+    pub next_unlock_amount: Uint128,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct WalletInfo {
     pub wallet: String,
     pub total: Uint128,
+    pub allocation: Uint128,
+    pub refunded: Uint128,
+    pub released: Uint128,
+}
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderBy {
+    Asc,
+    Desc,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StatsResponse {
+    pub total_deposited: Uint128,
+    pub depositors: u64,
+    pub total_allocated: Uint128,
+    pub total_refunded: Uint128,
+    pub total_returned: Uint128,
+    pub total_released: Uint128,
+    pub total_swept: Uint128,
 }
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SolvencyResponse {
+    // balance of token_addr against the allocations not released yet
+    pub token_balance: Uint128,
+    pub token_liability: Uint128,
+    pub token_surplus: Uint128,
+    pub token_shortfall: Uint128,
+    // refunds not returned yet, in the denoms they are paid in
+    pub denoms: Vec<DenomSolvency>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DenomSolvency {
+    pub denom: Denom,
+    pub balance: Uint128,
+    pub refund_liability: Uint128,
+    pub surplus: Uint128,
+    pub shortfall: Uint128,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct ListResponse {
     pub investors: Vec<WalletInfo>,
 }
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    // uploads allocations and drives the sale through its phases
+    Operator,
+    Pauser,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Operator => "operator",
+            Role::Pauser => "pauser",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RolesResponse {
+    // the admin, it changes the config and grants the other roles
+    pub owner: String,
+    pub operators: Vec<String>,
+    pub pausers: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    NotStarted,
+    DepositOpen,
+    Allocating,
+    Claiming,
+    Closed,
+    // the soft cap was missed, every depositor can claim their deposit back
+    Refunding,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PhaseResponse {
+    pub phase: Phase,
+    // seconds until the next phase starts, None when it needs an admin action or never comes
+    pub time_to_next_phase: Option<u64>,
+    pub paused: bool,
+    pub pause_reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AllowlistResponse {
+    pub allowed: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AcceptedDenomInfo {
+    pub denom: Denom,
+    pub price_source: PriceSource,
+    pub rate: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AcceptedDenomsResponse {
+    pub denoms: Vec<AcceptedDenomInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OperatorsResponse {
+    pub wallet: String,
+    pub operators: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Approval {
+    pub spender: String,
+    pub expires: Expiration,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OwnerOfResponse {
+    pub owner: String,
+    pub approvals: Vec<Approval>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ApprovedForAllResponse {
+    pub operators: Vec<Approval>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct NumTokensResponse {
+    pub count: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ContractInfoResponse {
+    pub name: String,
+    pub symbol: String,
+}
+
+// current state of the position behind the token
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PositionExtension {
+    pub investor: String,
+    pub allocation: Uint128,
+    pub released: Uint128,
+    pub refunded: Uint128,
+    pub returned_refunded: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct NftInfoResponse {
+    pub token_uri: Option<String>,
+    pub extension: PositionExtension,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AllNftInfoResponse {
+    pub access: OwnerOfResponse,
+    pub info: NftInfoResponse,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TokensResponse {
+    pub tokens: Vec<String>,
+}
+
+/// Hook sent by SendNft, matches cw721's ReceiveNft.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Cw721ReceiveMsg {
+    pub sender: String,
+    pub token_id: String,
+    pub msg: Binary,
+}
+
+impl Cw721ReceiveMsg {
+    pub fn into_cosmos_msg(self, contract_addr: String) -> StdResult<CosmosMsg> {
+        Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr,
+            msg: to_binary(&ReceiverExecuteMsg::ReceiveNft(self))?,
+            funds: vec![],
+        }))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+enum ReceiverExecuteMsg {
+    ReceiveNft(Cw721ReceiveMsg),
+}
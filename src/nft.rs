@@ -0,0 +1,451 @@
+use cosmwasm_std::{
+    attr, Addr, Binary, BlockInfo, Deps, DepsMut, Empty, Env, MessageInfo, Order, Response,
+    StdError, StdResult, Storage,
+};
+use cw20::Expiration;
+use cw_storage_plus::Bound;
+
+use crate::contract::{may_load_user_data, DEFAULT_LIMIT, MAX_LIMIT};
+use crate::error::ContractError;
+use crate::msg::{
+    AllNftInfoResponse, Approval, ApprovedForAllResponse, ContractInfoResponse, Cw721ReceiveMsg,
+    NftInfoResponse, NumTokensResponse, OwnerOfResponse, PositionExtension, TokensResponse,
+};
+use crate::state::{
+    Config, TokenInfo, CONFIG, DEPOSIT, FINALIZED_SALE, NFT_COUNT, NFT_OPERATORS, NFT_OWNER_TOKENS,
+    NFT_TOKENS, RELEASED_INFO, RETURNED_REFUNDED_INFO, STATS,
+};
+
+/// Mints the token of the investor's position when NFT mode is on and it does not exist yet.
+pub fn mint(storage: &mut dyn Storage, config: &Config, investor: &Addr) -> StdResult<bool> {
+    if config.position_nft.is_none() || NFT_TOKENS.has(storage, investor.as_str()) {
+        return Ok(false);
+    }
+    NFT_TOKENS.save(
+        storage,
+        investor.as_str(),
+        &TokenInfo {
+            owner: investor.clone(),
+            approvals: vec![],
+        },
+    )?;
+    NFT_OWNER_TOKENS.save(storage, (investor, investor.as_str()), &Empty {})?;
+    let count = NFT_COUNT.may_load(storage)?.unwrap_or_default();
+    NFT_COUNT.save(storage, &(count + 1))?;
+    Ok(true)
+}
+
+/// Whoever is paid for the position: the token owner in NFT mode, the investor otherwise.
+pub fn position_owner(storage: &dyn Storage, config: &Config, investor: &Addr) -> StdResult<Addr> {
+    if config.position_nft.is_none() {
+        return Ok(investor.clone());
+    }
+    Ok(NFT_TOKENS
+        .may_load(storage, investor.as_str())?
+        .map_or_else(|| investor.clone(), |token| token.owner))
+}
+
+// owner, an unexpired spender of the token or an unexpired operator of the owner
+pub fn can_send(
+    storage: &dyn Storage,
+    block: &BlockInfo,
+    sender: &Addr,
+    token: &TokenInfo,
+) -> StdResult<bool> {
+    if token.owner == *sender
+        || token
+            .approvals
+            .iter()
+            .any(|a| a.spender == sender.as_str() && !a.expires.is_expired(block))
+    {
+        return Ok(true);
+    }
+    can_approve(storage, block, sender, token)
+}
+
+fn can_approve(
+    storage: &dyn Storage,
+    block: &BlockInfo,
+    sender: &Addr,
+    token: &TokenInfo,
+) -> StdResult<bool> {
+    if token.owner == *sender {
+        return Ok(true);
+    }
+    Ok(NFT_OPERATORS
+        .may_load(storage, (&token.owner, sender))?
+        .is_some_and(|expires| !expires.is_expired(block)))
+}
+
+// a finalized sale gives every depositor a position, its token may not be minted yet
+fn finalized_positions(storage: &dyn Storage, config: &Config) -> StdResult<bool> {
+    Ok(config.position_nft.is_some() && FINALIZED_SALE.may_load(storage)?.is_some())
+}
+
+fn is_finalized_position(storage: &dyn Storage, token_id: &str) -> StdResult<bool> {
+    Ok(finalized_positions(storage, &CONFIG.load(storage)?)?
+        && DEPOSIT.has(storage, &Addr::unchecked(token_id)))
+}
+
+fn mint_finalized(
+    storage: &mut dyn Storage,
+    config: &Config,
+    token_id: &str,
+) -> Result<(), ContractError> {
+    if is_finalized_position(storage, token_id)? {
+        mint(storage, config, &Addr::unchecked(token_id))?;
+    }
+    Ok(())
+}
+
+// an unminted finalized position reads as a token still held by its depositor
+fn load_token(storage: &dyn Storage, token_id: &str) -> Result<TokenInfo, ContractError> {
+    if let Some(token) = NFT_TOKENS.may_load(storage, token_id)? {
+        return Ok(token);
+    }
+    if is_finalized_position(storage, token_id)? {
+        return Ok(TokenInfo {
+            owner: Addr::unchecked(token_id),
+            approvals: vec![],
+        });
+    }
+    Err(ContractError::TokenNotFound {
+        token_id: token_id.to_string(),
+    })
+}
+
+fn nft_enabled(config: &Config) -> Result<(), ContractError> {
+    if config.position_nft.is_none() {
+        return Err(ContractError::NftDisabled {});
+    }
+    Ok(())
+}
+
+fn transfer(
+    deps: DepsMut,
+    env: &Env,
+    sender: &Addr,
+    recipient: &Addr,
+    token_id: &str,
+) -> Result<(), ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    nft_enabled(&config)?;
+    mint_finalized(deps.storage, &config, token_id)?;
+    let mut token = load_token(deps.storage, token_id)?;
+    if !can_send(deps.storage, &env.block, sender, &token)? {
+        return Err(ContractError::Unauthorized {});
+    }
+    NFT_OWNER_TOKENS.remove(deps.storage, (&token.owner, token_id));
+    token.owner = recipient.clone();
+    token.approvals = vec![];
+    NFT_TOKENS.save(deps.storage, token_id, &token)?;
+    NFT_OWNER_TOKENS.save(deps.storage, (recipient, token_id), &Empty {})?;
+    Ok(())
+}
+
+pub fn transfer_nft(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    recipient: String,
+    token_id: String,
+) -> Result<Response, ContractError> {
+    let recipient = deps.api.addr_validate(&recipient)?;
+    transfer(deps, &env, &info.sender, &recipient, &token_id)?;
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "transfer_nft"),
+        attr("sender", info.sender),
+        attr("recipient", recipient),
+        attr("token_id", token_id),
+    ]))
+}
+
+pub fn send_nft(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    contract: String,
+    token_id: String,
+    msg: Binary,
+) -> Result<Response, ContractError> {
+    let contract = deps.api.addr_validate(&contract)?;
+    transfer(deps, &env, &info.sender, &contract, &token_id)?;
+    let receive_msg = Cw721ReceiveMsg {
+        sender: info.sender.to_string(),
+        token_id: token_id.clone(),
+        msg,
+    };
+    Ok(Response::new()
+        .add_message(receive_msg.into_cosmos_msg(contract.to_string())?)
+        .add_attributes(vec![
+            attr("action", "send_nft"),
+            attr("sender", info.sender),
+            attr("recipient", contract),
+            attr("token_id", token_id),
+        ]))
+}
+
+// sets (expires Some) or drops (None) the approval of spender on the token
+fn update_approvals(
+    deps: DepsMut,
+    env: &Env,
+    info: &MessageInfo,
+    spender: &str,
+    token_id: &str,
+    expires: Option<Expiration>,
+) -> Result<(), ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    nft_enabled(&config)?;
+    mint_finalized(deps.storage, &config, token_id)?;
+    let mut token = load_token(deps.storage, token_id)?;
+    if !can_approve(deps.storage, &env.block, &info.sender, &token)? {
+        return Err(ContractError::Unauthorized {});
+    }
+    let spender = deps.api.addr_validate(spender)?;
+    token.approvals.retain(|a| a.spender != spender.as_str());
+    if let Some(expires) = expires {
+        if expires.is_expired(&env.block) {
+            return Err(ContractError::Expired {});
+        }
+        token.approvals.push(Approval {
+            spender: spender.to_string(),
+            expires,
+        });
+    }
+    NFT_TOKENS.save(deps.storage, token_id, &token)?;
+    Ok(())
+}
+
+pub fn approve(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    spender: String,
+    token_id: String,
+    expires: Option<Expiration>,
+) -> Result<Response, ContractError> {
+    update_approvals(
+        deps,
+        &env,
+        &info,
+        &spender,
+        &token_id,
+        Some(expires.unwrap_or_default()),
+    )?;
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "approve"),
+        attr("sender", info.sender),
+        attr("spender", spender),
+        attr("token_id", token_id),
+    ]))
+}
+
+pub fn revoke(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    spender: String,
+    token_id: String,
+) -> Result<Response, ContractError> {
+    update_approvals(deps, &env, &info, &spender, &token_id, None)?;
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "revoke"),
+        attr("sender", info.sender),
+        attr("spender", spender),
+        attr("token_id", token_id),
+    ]))
+}
+
+pub fn approve_all(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    operator: String,
+    expires: Option<Expiration>,
+) -> Result<Response, ContractError> {
+    nft_enabled(&CONFIG.load(deps.storage)?)?;
+    let expires = expires.unwrap_or_default();
+    if expires.is_expired(&env.block) {
+        return Err(ContractError::Expired {});
+    }
+    let operator = deps.api.addr_validate(&operator)?;
+    NFT_OPERATORS.save(deps.storage, (&info.sender, &operator), &expires)?;
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "approve_all"),
+        attr("sender", info.sender),
+        attr("operator", operator),
+    ]))
+}
+
+pub fn revoke_all(
+    deps: DepsMut,
+    info: MessageInfo,
+    operator: String,
+) -> Result<Response, ContractError> {
+    nft_enabled(&CONFIG.load(deps.storage)?)?;
+    let operator = deps.api.addr_validate(&operator)?;
+    NFT_OPERATORS.remove(deps.storage, (&info.sender, &operator));
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "revoke_all"),
+        attr("sender", info.sender),
+        attr("operator", operator),
+    ]))
+}
+
+pub(crate) fn key_to_string(key: Vec<u8>) -> StdResult<String> {
+    String::from_utf8(key).map_err(|e| StdError::invalid_utf8(e.to_string()))
+}
+
+pub fn query_owner_of(
+    deps: Deps,
+    env: Env,
+    token_id: String,
+    include_expired: Option<bool>,
+) -> Result<OwnerOfResponse, ContractError> {
+    let token = load_token(deps.storage, &token_id)?;
+    let include_expired = include_expired.unwrap_or(false);
+    Ok(OwnerOfResponse {
+        owner: token.owner.to_string(),
+        approvals: token
+            .approvals
+            .into_iter()
+            .filter(|a| include_expired || !a.expires.is_expired(&env.block))
+            .collect(),
+    })
+}
+
+pub fn query_approved_for_all(
+    deps: Deps,
+    env: Env,
+    owner: String,
+    include_expired: Option<bool>,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<ApprovedForAllResponse> {
+    let owner = deps.api.addr_validate(&owner)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let include_expired = include_expired.unwrap_or(false);
+    let start = start_after.map(Bound::exclusive);
+    let mut operators = vec![];
+    for item in NFT_OPERATORS
+        .prefix(&owner)
+        .range(deps.storage, start, None, Order::Ascending)
+    {
+        let (key, expires) = item?;
+        if !include_expired && expires.is_expired(&env.block) {
+            continue;
+        }
+        operators.push(Approval {
+            spender: key_to_string(key)?,
+            expires,
+        });
+        if operators.len() == limit {
+            break;
+        }
+    }
+    Ok(ApprovedForAllResponse { operators })
+}
+
+pub fn query_num_tokens(deps: Deps) -> StdResult<NumTokensResponse> {
+    let count = if finalized_positions(deps.storage, &CONFIG.load(deps.storage)?)? {
+        STATS.load(deps.storage)?.depositors
+    } else {
+        NFT_COUNT.may_load(deps.storage)?.unwrap_or_default()
+    };
+    Ok(NumTokensResponse { count })
+}
+
+pub fn query_contract_info(deps: Deps) -> Result<ContractInfoResponse, ContractError> {
+    let info = CONFIG
+        .load(deps.storage)?
+        .position_nft
+        .ok_or(ContractError::NftDisabled {})?;
+    Ok(ContractInfoResponse {
+        name: info.name,
+        symbol: info.symbol,
+    })
+}
+
+pub fn query_nft_info(deps: Deps, token_id: String) -> Result<NftInfoResponse, ContractError> {
+    load_token(deps.storage, &token_id)?;
+    let investor = Addr::unchecked(token_id);
+    let user_data = may_load_user_data(deps.storage, &investor)?.unwrap_or_default();
+    Ok(NftInfoResponse {
+        token_uri: None,
+        extension: PositionExtension {
+            investor: investor.to_string(),
+            allocation: user_data.allocation,
+            released: RELEASED_INFO
+                .may_load(deps.storage, &investor)?
+                .unwrap_or_default(),
+            refunded: user_data.refunded,
+            returned_refunded: RETURNED_REFUNDED_INFO
+                .may_load(deps.storage, &investor)?
+                .unwrap_or_default(),
+        },
+    })
+}
+
+pub fn query_all_nft_info(
+    deps: Deps,
+    env: Env,
+    token_id: String,
+    include_expired: Option<bool>,
+) -> Result<AllNftInfoResponse, ContractError> {
+    Ok(AllNftInfoResponse {
+        access: query_owner_of(deps, env, token_id.clone(), include_expired)?,
+        info: query_nft_info(deps, token_id)?,
+    })
+}
+
+pub fn query_tokens(
+    deps: Deps,
+    owner: String,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<TokensResponse> {
+    let owner = deps.api.addr_validate(&owner)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.clone().map(Bound::exclusive);
+    let mut tokens: Vec<String> = NFT_OWNER_TOKENS
+        .prefix(&owner)
+        .keys(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(key_to_string)
+        .collect::<StdResult<_>>()?;
+    // the owner's own position when its token is not minted yet
+    let own_token = owner.to_string();
+    if start_after.is_none_or(|start_after| own_token > start_after)
+        && !NFT_TOKENS.has(deps.storage, &own_token)
+        && is_finalized_position(deps.storage, &own_token)?
+    {
+        let index = tokens.partition_point(|t| *t < own_token);
+        tokens.insert(index, own_token);
+        tokens.truncate(limit);
+    }
+    Ok(TokensResponse { tokens })
+}
+
+pub fn query_all_tokens(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<TokensResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+    // token ids are the depositor addresses, minted or not
+    let tokens = if finalized_positions(deps.storage, &CONFIG.load(deps.storage)?)? {
+        DEPOSIT
+            .keys(deps.storage, start, None, Order::Ascending)
+            .take(limit)
+            .map(key_to_string)
+            .collect::<StdResult<_>>()?
+    } else {
+        NFT_TOKENS
+            .keys(deps.storage, start, None, Order::Ascending)
+            .take(limit)
+            .map(key_to_string)
+            .collect::<StdResult<_>>()?
+    };
+    Ok(TokensResponse { tokens })
+}
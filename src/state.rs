@@ -1,5 +1,7 @@
-use crate::msg::DepositInfo;
-use cosmwasm_std::{Addr, CanonicalAddr, Uint128};
+use crate::msg::{AcceptedDenom, Approval, DenomDeposit, DepositInfo, PositionNftInfo};
+use crate::vesting::{ScheduleType, VestingSchedule};
+use cosmwasm_std::{Addr, CanonicalAddr, Decimal, Empty, Uint128};
+use cw20::{Denom, Expiration};
 use cw_storage_plus::{Item, Map};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -7,18 +9,58 @@ use serde::{Deserialize, Serialize};
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Config {
     pub admin: CanonicalAddr,
+    // proposed by the admin, becomes admin with AcceptAdmin
+    pub pending_admin: Option<CanonicalAddr>,
     pub team_wallet: CanonicalAddr,
     pub token_addr: CanonicalAddr,
     pub stable_denom: String,
+    // CW20 stable raised instead of the native stable_denom when set
+    pub stable_token: Option<CanonicalAddr>,
+    pub staking_contract: Option<CanonicalAddr>,
+    // TGE
     pub start_time: u64,
+    // cliff after TGE
     pub lock_time: u64,
+    // linear vesting after the cliff
     pub vesting_time: u64,
+    // share of the allocation unlocked at TGE, in basis points
+    pub tge_unlock_bps: u64,
+    pub schedule_type: ScheduleType,
+    pub deposit_start_time: u64,
+    pub deposit_end_time: u64,
+    pub hard_cap: Uint128,
+    pub soft_cap: Uint128,
+    pub min_deposit: Uint128,
+    pub max_deposit_per_wallet: Option<Uint128>,
+    // hex encoded root of the allowlist tree, anyone can deposit when it is not set
+    pub merkle_root: Option<String>,
+    // TransferPosition, on unless the admin switched it off
+    pub position_transfers_enabled: bool,
+    // positions are CW721 tokens when set
+    pub position_nft: Option<PositionNftInfo>,
+}
+
+impl Config {
+    pub fn vesting_schedule(&self) -> VestingSchedule {
+        VestingSchedule {
+            start_time: self.start_time,
+            lock_time: self.lock_time,
+            vesting_time: self.vesting_time,
+            tge_unlock_bps: self.tge_unlock_bps,
+            schedule_type: self.schedule_type.clone(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
-pub struct Status {
-    pub can_deposit: bool,
-    pub can_withdraw: bool,
+pub struct State {
+    // set by EnableWithdraw, moves the sale from Allocating to Claiming
+    pub claim_start_time: Option<u64>,
+    // normalized sum of all accepted deposits, never more than the hard cap
+    pub total_deposited: Uint128,
+    // set by a pauser, blocks deposits, claims and position moves
+    pub paused: bool,
+    pub pause_reason: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -27,23 +69,116 @@ pub struct DepositData {
     pub deposit_history: Vec<DepositInfo>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
 pub struct UserData {
     pub allocation: Uint128,
     pub refunded: Uint128,
 }
+// running totals behind the Stats query, amounts normalized to the stable asset
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct Stats {
+    pub depositors: u64,
+    pub total_allocated: Uint128,
+    pub total_refunded: Uint128,
+    // part of total_refunded already paid back
+    pub total_returned: Uint128,
+    pub total_released: Uint128,
+    // deposits kept for the team, net of clawbacks
+    pub total_swept: Uint128,
+}
+
+// per denom, refunds booked for investors and the part already paid back; the Solvency
+// query reads these instead of walking every wallet
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DenomRefunds {
+    pub denom: Denom,
+    pub owed: Uint128,
+    pub returned: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FinalizedSale {
+    // stable amount paid for one token
+    pub token_price: Decimal,
+    pub tokens_for_sale: Uint128,
+    pub total_deposited: Uint128,
+    // part of total_deposited that buys tokens and goes to the team, the rest is refunded
+    pub accepted_amount: Uint128,
+}
+
 pub const CONFIG: Item<Config> = Item::new("config");
-pub const STATUS: Item<Status> = Item::new("status");
+pub const STATE: Item<State> = Item::new("state");
+pub const STATS: Item<Stats> = Item::new("stats");
 
+// allocations computed on-chain by Finalize instead of uploaded through Update
+pub const FINALIZED_SALE: Item<FinalizedSale> = Item::new("finalized_sale");
+
+// deposit amounts and history are normalized to the stable asset
 pub const DEPOSIT: Map<&Addr, DepositData> = Map::new("deposit_info");
 
+// denoms accepted besides the stable asset, keyed by native denom or CW20 address
+pub const ACCEPTED_DENOMS: Map<&str, AcceptedDenom> = Map::new("accepted_denoms");
+// what each wallet deposited in each denom, refunds and team sweeps are split along it
+pub const DENOM_DEPOSIT: Map<(&Addr, &str), DenomDeposit> = Map::new("denom_deposit");
+pub const DENOM_TOTAL: Map<&str, DenomDeposit> = Map::new("denom_total");
+pub const DENOM_REFUNDS: Map<&str, DenomRefunds> = Map::new("denom_refunds");
+
 // ( allocation, refunded)
 pub const USER_DATA: Map<&Addr, UserData> = Map::new("user_data");
 
+// (investor, operator) pairs, the operator may claim for the investor
+pub const OPERATORS: Map<(&Addr, &Addr), Empty> = Map::new("operators");
+
+// (role, address) pairs granted by the admin
+pub const ROLES: Map<(&str, &Addr), Empty> = Map::new("roles");
+
+// per-investor override of the schedule in Config
+pub const USER_SCHEDULE: Map<&Addr, VestingSchedule> = Map::new("user_schedule");
+
 // allocation
 pub const RELEASED_INFO: Map<&Addr, Uint128> = Map::new("released_info");
 //refunded
 pub const RETURNED_REFUNDED_INFO: Map<&Addr, Uint128> = Map::new("refunded_info");
+// deposit already sent to the team wallet, per denom
+pub const SWEPT_INFO: Map<(&Addr, &str), Uint128> = Map::new("swept_info");
+// per denom, sent to the team but later given back to investors as refund;
+// netted against the next team transfers
+pub const SWEEP_DEBT: Map<&str, Uint128> = Map::new("sweep_debt");
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TokenInfo {
+    pub owner: Addr,
+    pub approvals: Vec<Approval>,
+}
+
+// position tokens keyed by token_id, the original investor address
+pub const NFT_TOKENS: Map<&str, TokenInfo> = Map::new("nft_tokens");
+// (owner, token_id)
+pub const NFT_OWNER_TOKENS: Map<(&Addr, &str), Empty> = Map::new("nft_owner_tokens");
+// (owner, operator) => expiration
+pub const NFT_OPERATORS: Map<(&Addr, &Addr), Expiration> = Map::new("nft_operators");
+pub const NFT_COUNT: Item<u64> = Item::new("nft_count");
+
+// layout before cw2 versioning, only read by migrate
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LegacyConfig {
+    pub admin: CanonicalAddr,
+    pub team_wallet: CanonicalAddr,
+    pub token_addr: CanonicalAddr,
+    pub stable_denom: String,
+    pub start_time: u64,
+    pub lock_time: u64,
+    pub vesting_time: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LegacyStatus {
+    pub can_deposit: bool,
+    pub can_withdraw: bool,
+}
+
+pub const LEGACY_CONFIG: Item<LegacyConfig> = Item::new("config");
+pub const LEGACY_STATUS: Item<LegacyStatus> = Item::new("status");
 
 // pub const LOCK_TIME: u64 = 2592000; // 30DAYS in seconds
 
@@ -0,0 +1,114 @@
+use crate::msg::{OraclePriceResponse, OracleQueryMsg};
+use cosmwasm_std::testing::{MockApi, MockQuerier, MockStorage, MOCK_CONTRACT_ADDR};
+use cosmwasm_std::{
+    from_binary, from_slice, to_binary, Coin, ContractResult, Decimal, Empty, OwnedDeps, Querier,
+    QuerierResult, QueryRequest, SystemError, SystemResult, Uint128, WasmQuery,
+};
+use cw20::{BalanceResponse, Cw20QueryMsg};
+use std::collections::HashMap;
+
+/// mock_dependencies with a querier that also answers smart queries of mocked contracts
+pub fn mock_dependencies(
+    contract_balance: &[Coin],
+) -> OwnedDeps<MockStorage, MockApi, WasmMockQuerier> {
+    let custom_querier: WasmMockQuerier =
+        WasmMockQuerier::new(MockQuerier::new(&[(MOCK_CONTRACT_ADDR, contract_balance)]));
+
+    OwnedDeps {
+        storage: MockStorage::default(),
+        api: MockApi::default(),
+        querier: custom_querier,
+    }
+}
+
+pub struct WasmMockQuerier {
+    base: MockQuerier<Empty>,
+    // oracle contract => denom => rate
+    oracle_rates: HashMap<String, HashMap<String, Decimal>>,
+    // cw20 contract => holder => balance
+    token_balances: HashMap<String, HashMap<String, Uint128>>,
+}
+
+impl Querier for WasmMockQuerier {
+    fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+        let request: QueryRequest<Empty> = match from_slice(bin_request) {
+            Ok(v) => v,
+            Err(e) => {
+                return SystemResult::Err(SystemError::InvalidRequest {
+                    error: format!("Parsing query request: {}", e),
+                    request: bin_request.into(),
+                })
+            }
+        };
+        self.handle_query(&request)
+    }
+}
+
+impl WasmMockQuerier {
+    pub fn new(base: MockQuerier<Empty>) -> Self {
+        WasmMockQuerier {
+            base,
+            oracle_rates: HashMap::new(),
+            token_balances: HashMap::new(),
+        }
+    }
+
+    pub fn with_oracle_rate(&mut self, oracle: &str, denom: &str, rate: Decimal) {
+        self.oracle_rates
+            .entry(oracle.to_string())
+            .or_default()
+            .insert(denom.to_string(), rate);
+    }
+
+    pub fn with_balance(&mut self, address: &str, balance: Vec<Coin>) {
+        self.base.update_balance(address, balance);
+    }
+
+    pub fn with_token_balance(&mut self, token: &str, address: &str, balance: Uint128) {
+        self.token_balances
+            .entry(token.to_string())
+            .or_default()
+            .insert(address.to_string(), balance);
+    }
+
+    pub fn handle_query(&self, request: &QueryRequest<Empty>) -> QuerierResult {
+        match request {
+            QueryRequest::Wasm(WasmQuery::Smart { contract_addr, msg })
+                if self.token_balances.contains_key(contract_addr) =>
+            {
+                let balances = &self.token_balances[contract_addr];
+                match from_binary(msg) {
+                    Ok(Cw20QueryMsg::Balance { address }) => {
+                        SystemResult::Ok(ContractResult::from(to_binary(&BalanceResponse {
+                            balance: balances.get(&address).copied().unwrap_or_default(),
+                        })))
+                    }
+                    Ok(_) => SystemResult::Ok(ContractResult::Err("Unsupported query".to_string())),
+                    Err(e) => SystemResult::Ok(ContractResult::Err(e.to_string())),
+                }
+            }
+            QueryRequest::Wasm(WasmQuery::Smart { contract_addr, msg }) => {
+                let rates = match self.oracle_rates.get(contract_addr) {
+                    Some(rates) => rates,
+                    None => {
+                        return SystemResult::Err(SystemError::NoSuchContract {
+                            addr: contract_addr.clone(),
+                        })
+                    }
+                };
+                match from_binary(msg) {
+                    Ok(OracleQueryMsg::Price { denom }) => match rates.get(&denom) {
+                        Some(rate) => SystemResult::Ok(ContractResult::from(to_binary(
+                            &OraclePriceResponse { rate: *rate },
+                        ))),
+                        None => {
+                            SystemResult::Ok(ContractResult::Err(format!("No price for {}", denom)))
+                        }
+                    },
+                    Err(e) => SystemResult::Ok(ContractResult::Err(e.to_string())),
+                }
+            }
+            _ => self.base.handle_query(request),
+        }
+    }
+}
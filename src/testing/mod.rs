@@ -1,2 +1,4 @@
 #[cfg(test)]
+mod mock_querier;
+#[cfg(test)]
 mod tests;
@@ -1,11 +1,69 @@
-use crate::contract::{execute, instantiate, query};
-use crate::msg::ExecuteMsg::{CanDeposit, Claim, Deposit, EnableWithdraw, Update};
-use crate::msg::{DepositInfo, InstantiateMsg, InvestorResponse, QueryMsg, UserUpdateData};
-use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+use crate::contract::{execute, instantiate, migrate, query};
+use crate::msg::ExecuteMsg::{
+    AcceptAdmin, Approve, ApproveOperator, CancelAdminProposal, Claim, ClaimAndStake, Deposit,
+    Distribute, EnableWithdraw, Finalize, GrantRole, MintPositions, Pause, ProposeNewAdmin,
+    Receive, RegisterMerkleRoot, RevokeOperator, RevokeRole, TransferNft, TransferPosition,
+    Unpause, Update, UpdateAcceptedDenom, UpdateDepositLimits, UpdatePositionTransfers,
+    UpdateSchedule, UpdateStakingContract, UpdateTeamWallet,
+};
+use crate::msg::{
+    AcceptedDenomsResponse, AllowlistResponse, ClaimableAmountResponse, ConfigResponse,
+    Cw20HookMsg, DenomDeposit, DenomSolvency, DepositInfo, InstantiateMsg, InvestorResponse,
+    LegacyMigrateMsg, ListResponse, MigrateMsg, NftInfoResponse, NumTokensResponse,
+    OperatorsResponse, OrderBy, OwnerOfResponse, Phase, PhaseResponse, PositionExtension,
+    PositionNftInfo, PriceSource, QueryMsg, Role, RolesResponse, SolvencyResponse, StatsResponse,
+    TokensResponse, UserUpdateData, WalletInfo,
+};
+use crate::state::{
+    DenomRefunds, DepositData, LegacyConfig, LegacyStatus, UserData, DENOM_REFUNDS, DEPOSIT,
+    LEGACY_CONFIG, LEGACY_STATUS, USER_DATA,
+};
+use crate::testing::mock_querier::mock_dependencies as mock_dependencies_with_querier;
+use crate::vesting::{Checkpoint, ScheduleType, VestingSchedule};
+use crate::ContractError;
+use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info, MOCK_CONTRACT_ADDR};
 use cosmwasm_std::{
-    attr, coin, from_binary, to_binary, BankMsg, CosmosMsg, Response, StdError, Uint128, WasmMsg,
+    attr, coin, from_binary, to_binary, Addr, Api, BankMsg, CosmosMsg, Decimal, DepsMut, Env,
+    Response, Uint128, WasmMsg,
 };
-use cw20::Cw20ExecuteMsg;
+use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg, Denom};
+use sha2::Digest;
+
+const DEPOSIT_PERIOD: u64 = 86400; // 1 day
+const LOCK_TIME: u64 = 2592000; // 30 days
+const VESTING_TIME: u64 = 7776000; // 90 days
+
+// deposits open at the mock block time and vesting starts right after the window closes
+fn init_msg(env: &Env) -> InstantiateMsg {
+    let now = env.block.time.seconds();
+    InstantiateMsg {
+        token_addr: "sayve_token".to_string(),
+        stable_denom: "uusd".to_string(),
+        stable_token: None,
+        staking_contract: None,
+        admin: Some("admin1".to_string()),
+        team_wallet: Some("team_wallet".to_string()),
+        start_time: Uint128::from(now + DEPOSIT_PERIOD),
+        lock_time: Uint128::from(LOCK_TIME),
+        vesting_time: Uint128::from(VESTING_TIME),
+        tge_unlock_bps: 0,
+        schedule_type: None,
+        deposit_start_time: Uint128::from(now),
+        deposit_end_time: Uint128::from(now + DEPOSIT_PERIOD),
+        hard_cap: Uint128::from(1000000u128),
+        soft_cap: Uint128::zero(),
+        min_deposit: Uint128::zero(),
+        max_deposit_per_wallet: None,
+        merkle_root: None,
+        position_nft: None,
+    }
+}
+
+fn env_at(seconds_later: u64) -> Env {
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(seconds_later);
+    env
+}
 
 #[test]
 fn test_instantiate() {
@@ -13,17 +71,17 @@ fn test_instantiate() {
     let mut deps = mock_dependencies(&[]);
     let env = mock_env();
     let info = mock_info(owner, &[]);
-    let msg = InstantiateMsg {
-        token_addr: "SAYVE_TOKEN".to_string(),
-        stable_denom: "uusd".to_string(),
-        admin: Some("ADMIN1".to_string()),
-        team_wallet: Some("TEAM_WALLET".to_string()),
-    };
-    let res = instantiate(deps.as_mut(), env, info, msg).unwrap();
+    let msg = init_msg(&env);
+    let res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
     assert_eq!(
         res,
         Response::new().add_attributes(vec![attr("action", "instantiate")])
-    )
+    );
+
+    let mut msg = init_msg(&env);
+    msg.deposit_end_time = msg.deposit_start_time;
+    let res = instantiate(deps.as_mut(), env, info, msg).unwrap_err();
+    assert_eq!(res, ContractError::InvalidDepositWindow {});
 }
 
 #[test]
@@ -32,208 +90,3889 @@ fn test_deposit() {
     let mut deps = mock_dependencies(&[]);
     let env = mock_env();
     let info = mock_info(owner, &[]);
-    let init_msg = InstantiateMsg {
-        token_addr: "SAYVE_TOKEN".to_string(),
-        stable_denom: "uusd".to_string(),
-        admin: Some("ADMIN1".to_string()),
-        team_wallet: Some("TEAM_WALLET".to_string()),
+    let mut init = init_msg(&env);
+    init.deposit_start_time = Uint128::from(env.block.time.seconds() + 100);
+    let _res = instantiate(deps.as_mut(), env.clone(), info, init).unwrap();
+
+    // window not open yet
+    let deposit_msg = Deposit {
+        cap: None,
+        proof: None,
     };
-    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), init_msg).unwrap();
+    let info = mock_info("user1", &[coin(1000, "uusd")]);
+    let res = execute(deps.as_mut(), env, info, deposit_msg.clone()).unwrap_err();
+    assert_eq!(res, ContractError::DepositClosed {});
+
+    let env = env_at(100);
+    let info = mock_info("user1", &[]);
+    let res = execute(deps.as_mut(), env.clone(), info, deposit_msg.clone()).unwrap_err();
+    assert_eq!(
+        res,
+        ContractError::InvalidFunds {
+            denom: "uusd".to_string(),
+        }
+    );
+
+    let info = mock_info("user1", &[coin(10000u128, "uusd")]);
+    let res = execute(deps.as_mut(), env, info, deposit_msg.clone()).unwrap();
+    assert_eq!(
+        res,
+        Response::new().add_attributes(vec![attr("action", "deposit"), attr("amount", "10000")])
+    );
+
+    // window closed
+    let env = env_at(DEPOSIT_PERIOD);
+    let info = mock_info("user1", &[coin(10000u128, "uusd")]);
+    let res = execute(deps.as_mut(), env, info, deposit_msg).unwrap_err();
+    assert_eq!(res, ContractError::DepositClosed {});
+}
+
+#[test]
+fn test_hard_cap() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+    let mut init = init_msg(&env);
+    init.hard_cap = Uint128::from(15000u128);
+    let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), init).unwrap();
+
+    let info = mock_info("user1", &[coin(10000, "uusd")]);
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info,
+        Deposit {
+            cap: None,
+            proof: None,
+        },
+    )
+    .unwrap();
 
-    let deposit_msg = Deposit {};
-    let info = mock_info("USER1", &[coin(1000, "uusd")]);
+    // only 5000 fits under the cap, the rest is sent back
+    let info = mock_info("user2", &[coin(10000, "uusd")]);
     let res = execute(
         deps.as_mut(),
         env.clone(),
-        info.clone(),
-        deposit_msg.clone(),
+        info,
+        Deposit {
+            cap: None,
+            proof: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        res,
+        Response::new()
+            .add_message(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "user2".to_string(),
+                amount: vec![coin(5000, "uusd")]
+            }))
+            .add_attributes(vec![
+                attr("action", "deposit"),
+                attr("amount", "5000"),
+                attr("refund_amount", "5000"),
+            ])
+    );
+
+    let info = mock_info("user3", &[coin(10000, "uusd")]);
+    let res = execute(
+        deps.as_mut(),
+        env,
+        info,
+        Deposit {
+            cap: None,
+            proof: None,
+        },
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::HardCapReached {});
+
+    let mut init = init_msg(&mock_env());
+    init.soft_cap = Uint128::from(20000u128);
+    init.hard_cap = Uint128::from(10000u128);
+    let res = instantiate(
+        mock_dependencies(&[]).as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        init,
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::InvalidCaps {});
+}
+
+#[test]
+fn test_deposit_limits() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+    let mut init = init_msg(&env);
+    init.min_deposit = Uint128::from(100u128);
+    init.max_deposit_per_wallet = Some(Uint128::from(15000u128));
+    let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), init).unwrap();
+
+    let info = mock_info("user1", &[coin(99, "uusd")]);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info,
+        Deposit {
+            cap: None,
+            proof: None,
+        },
     )
     .unwrap_err();
-    assert_eq!(res, StdError::generic_err("User is not able to deposit"));
+    assert_eq!(
+        res,
+        ContractError::BelowMinDeposit {
+            min_deposit: Uint128::from(100u128),
+        }
+    );
 
-    //check deposit
-    let can_deposit_msg = CanDeposit(true);
-    let info = mock_info("ADMIN1", &[]);
+    let info = mock_info("user1", &[coin(10000, "uusd")]);
     let _res = execute(
         deps.as_mut(),
         env.clone(),
         info.clone(),
-        can_deposit_msg.clone(),
+        Deposit {
+            cap: None,
+            proof: None,
+        },
+    )
+    .unwrap();
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        Deposit {
+            cap: None,
+            proof: None,
+        },
     )
     .unwrap();
-
-    let info = mock_info("USER1", &[]);
-    let res = execute(deps.as_mut(), env.clone(), info, deposit_msg.clone()).unwrap_err();
     assert_eq!(
         res,
-        StdError::generic_err("No uusd assets are provided to deposit")
+        Response::new()
+            .add_message(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "user1".to_string(),
+                amount: vec![coin(5000, "uusd")]
+            }))
+            .add_attributes(vec![
+                attr("action", "deposit"),
+                attr("amount", "5000"),
+                attr("refund_amount", "5000"),
+            ])
     );
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        Deposit {
+            cap: None,
+            proof: None,
+        },
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::WalletLimitReached {});
+
+    // the admin lifts the wallet limit while the sale runs
+    let limits_msg = UpdateDepositLimits {
+        min_deposit: Uint128::from(1000u128),
+        max_deposit_per_wallet: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("user1", &[]),
+        limits_msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("admin1", &[]),
+        limits_msg,
+    )
+    .unwrap();
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("admin1", &[]),
+        UpdateDepositLimits {
+            min_deposit: Uint128::from(1000u128),
+            max_deposit_per_wallet: Some(Uint128::from(999u128)),
+        },
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::InvalidDepositLimits {});
+
+    let res: ConfigResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::Config {}).unwrap()).unwrap();
+    assert_eq!(res.min_deposit, Uint128::from(1000u128));
+    assert_eq!(res.max_deposit_per_wallet, None);
 
-    let info = mock_info("USER1", &[coin(10000u128, "uusd")]);
-    let res = execute(deps.as_mut(), env.clone(), info, deposit_msg.clone()).unwrap();
+    let res = execute(
+        deps.as_mut(),
+        env,
+        info,
+        Deposit {
+            cap: None,
+            proof: None,
+        },
+    )
+    .unwrap();
     assert_eq!(
         res,
         Response::new().add_attributes(vec![attr("action", "deposit"), attr("amount", "10000")])
     );
 }
 
+fn sha256(data: &[u8]) -> [u8; 32] {
+    sha2::Sha256::digest(data).into()
+}
+
 #[test]
-fn test_claim() {
+fn test_merkle_allowlist() {
     let mut deps = mock_dependencies(&[]);
     let env = mock_env();
-    let info = mock_info("creator", &[]);
-    let init_msg = InstantiateMsg {
-        token_addr: "SAYVE_TOKEN".to_string(),
-        stable_denom: "uusd".to_string(),
-        admin: Some("ADMIN1".to_string()),
-        team_wallet: Some("TEAM_WALLET".to_string()),
-    };
-    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), init_msg).unwrap();
-
-    //check deposit
-    let can_deposit_msg = CanDeposit(true);
-    let info = mock_info("ADMIN1", &[]);
-    let _res = execute(
+    let _res = instantiate(
         deps.as_mut(),
         env.clone(),
-        info.clone(),
-        can_deposit_msg.clone(),
+        mock_info("creator", &[]),
+        init_msg(&env),
     )
     .unwrap();
 
-    //can_deposit
-    let can_deposit_msg = CanDeposit(true);
-    let info = mock_info("ADMIN1", &[]);
+    // two leaves: user1 without a cap, user2 capped at 5000
+    let leaf1 = sha256(b"user1");
+    let leaf2 = sha256(b"user25000");
+    let mut pair = [leaf1, leaf2];
+    pair.sort_unstable();
+    let root = hex::encode(sha256(&pair.concat()));
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("admin1", &[]),
+        RegisterMerkleRoot {
+            merkle_root: Some("not hex".to_string()),
+        },
+    )
+    .unwrap_err();
+    assert_eq!(
+        res,
+        ContractError::InvalidMerkleHash {
+            hash: "not hex".to_string(),
+        }
+    );
     let _res = execute(
         deps.as_mut(),
         env.clone(),
-        info.clone(),
-        can_deposit_msg.clone(),
+        mock_info("admin1", &[]),
+        RegisterMerkleRoot {
+            merkle_root: Some(root),
+        },
     )
     .unwrap();
 
-    //deposit
-    let deposit_msg = Deposit {};
-    let info = mock_info("USER1", &[coin(10000, "uusd")]);
-    let _res = execute(
+    let res = execute(
         deps.as_mut(),
         env.clone(),
-        info.clone(),
-        deposit_msg.clone(),
+        mock_info("user3", &[coin(1000, "uusd")]),
+        Deposit {
+            cap: None,
+            proof: Some(vec![hex::encode(leaf1)]),
+        },
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::NotAllowlisted {});
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("user1", &[coin(10000, "uusd")]),
+        Deposit {
+            cap: None,
+            proof: Some(vec![hex::encode(leaf2)]),
+        },
     )
     .unwrap();
+    assert_eq!(
+        res,
+        Response::new().add_attributes(vec![attr("action", "deposit"), attr("amount", "10000")])
+    );
 
-    //update
-    let update_msg = Update(vec![UserUpdateData {
-        user_addr: "USER1".to_string(),
-        allocation: Uint128::from(10000u128),
-        refunded: Uint128::from(1000u128),
-    }]);
-    let info = mock_info("ADMIN1", &[]);
-    let res = execute(deps.as_mut(), env.clone(), info.clone(), update_msg.clone()).unwrap();
+    // claiming a bigger cap than the one in the tree fails the proof
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("user2", &[coin(10000, "uusd")]),
+        Deposit {
+            cap: Some(Uint128::from(10000u128)),
+            proof: Some(vec![hex::encode(leaf1)]),
+        },
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::NotAllowlisted {});
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("user2", &[coin(10000, "uusd")]),
+        Deposit {
+            cap: Some(Uint128::from(5000u128)),
+            proof: Some(vec![hex::encode(leaf1)]),
+        },
+    )
+    .unwrap();
     assert_eq!(
         res,
         Response::new()
-            .add_messages(vec![CosmosMsg::Bank(BankMsg::Send {
-                to_address: "TEAM_WALLET".to_string(),
-                amount: vec![coin(9000, "uusd")]
-            })])
+            .add_message(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "user2".to_string(),
+                amount: vec![coin(5000, "uusd")]
+            }))
             .add_attributes(vec![
-                attr("action", "update"),
-                attr("transfer_amount", "9000")
+                attr("action", "deposit"),
+                attr("amount", "5000"),
+                attr("refund_amount", "5000"),
             ])
     );
 
-    //start_deposit
+    let msg = QueryMsg::VerifyAllowlist {
+        wallet: "user2".to_string(),
+        cap: Some(Uint128::from(5000u128)),
+        proof: vec![hex::encode(leaf1)],
+    };
+    let res: AllowlistResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+    assert_eq!(res, AllowlistResponse { allowed: true });
 
-    //can_withdraw
+    let msg = QueryMsg::VerifyAllowlist {
+        wallet: "user3".to_string(),
+        cap: None,
+        proof: vec![hex::encode(leaf1)],
+    };
+    let res: AllowlistResponse = from_binary(&query(deps.as_ref(), env, msg).unwrap()).unwrap();
+    assert_eq!(res, AllowlistResponse { allowed: false });
+}
 
-    //claim
-    let can_withdraw = EnableWithdraw {};
-    let info = mock_info("ADMIN1", &[]);
+#[test]
+fn test_soft_cap_refund() {
+    let mut deps = mock_dependencies(&[]);
     let env = mock_env();
+    let mut init = init_msg(&env);
+    init.soft_cap = Uint128::from(20000u128);
+    let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), init).unwrap();
+
+    let info = mock_info("user1", &[coin(10000, "uusd")]);
     let _res = execute(
         deps.as_mut(),
-        env.clone(),
-        info.clone(),
-        can_withdraw.clone(),
+        env,
+        info,
+        Deposit {
+            cap: None,
+            proof: None,
+        },
     )
     .unwrap();
 
-    let claim_msg = Claim {};
-    let info = mock_info("USER1", &[]);
-    let mut env = mock_env();
-    let time_stamp = env.clone().block.time.plus_seconds(26784000); //31 days later.
+    let env = env_at(DEPOSIT_PERIOD);
+    let res: PhaseResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::Phase {}).unwrap()).unwrap();
+    assert_eq!(
+        res,
+        PhaseResponse {
+            phase: Phase::Refunding,
+            time_to_next_phase: None,
+            paused: false,
+            pause_reason: None,
+        }
+    );
 
-    env.block.time = time_stamp;
+    let update_msg = Update(vec![UserUpdateData {
+        user_addr: "user1".to_string(),
+        allocation: Uint128::from(10000u128),
+        refunded: Uint128::zero(),
+        schedule: None,
+        clear_schedule: None,
+    }]);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("admin1", &[]),
+        update_msg,
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::SoftCapMissed {});
 
-    let res = execute(deps.as_mut(), env.clone(), info.clone(), claim_msg.clone()).unwrap();
+    let user = mock_info("user1", &[]);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        user.clone(),
+        Claim {
+            investor: None,
+            recipient: None,
+        },
+    )
+    .unwrap();
     assert_eq!(
         res,
         Response::new()
-            .add_messages(vec![
-                CosmosMsg::Bank(BankMsg::Send {
-                    to_address: "USER1".to_string(),
-                    amount: vec![coin(1000u128, "uusd")]
-                }),
-                CosmosMsg::Wasm(WasmMsg::Execute {
-                    contract_addr: "SAYVE_TOKEN".to_string(),
-                    msg: to_binary(&Cw20ExecuteMsg::Transfer {
-                        recipient: "USER1".to_string(),
-                        amount: Uint128::from(3333u128),
-                    })
-                    .unwrap(),
-                    funds: vec![]
-                }),
-            ])
+            .add_message(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "user1".to_string(),
+                amount: vec![coin(10000, "uusd")]
+            }))
             .add_attributes(vec![
                 attr("action", "claim"),
-                attr("claim_amount", "3333"),
-                attr("returned_refunded_amount", "1000"),
+                attr("claim_amount", "0"),
+                attr("returned_refunded_amount", "10000"),
             ])
     );
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        user,
+        Claim {
+            investor: None,
+            recipient: None,
+        },
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::AlreadyRefunded {});
+
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("user2", &[]),
+        Claim {
+            investor: None,
+            recipient: None,
+        },
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::NothingToRefund {});
+}
+
+#[test]
+fn test_phase() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+    let info = mock_info("creator", &[]);
+    let mut init = init_msg(&env);
+    init.deposit_start_time = Uint128::from(env.block.time.seconds() + 100);
+    let _res = instantiate(deps.as_mut(), env.clone(), info, init).unwrap();
 
-    let time_stamp = env.clone().block.time.plus_seconds(86400); //1 days later.
-    let mut env = env.clone();
-    env.block.time = time_stamp;
-    let res = execute(deps.as_mut(), env.clone(), info.clone(), claim_msg.clone()).unwrap();
+    let res: PhaseResponse =
+        from_binary(&query(deps.as_ref(), env, QueryMsg::Phase {}).unwrap()).unwrap();
     assert_eq!(
         res,
-        Response::new()
-            .add_messages(vec![CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: "SAYVE_TOKEN".to_string(),
-                msg: to_binary(&Cw20ExecuteMsg::Transfer {
-                    recipient: "USER1".to_string(),
-                    amount: Uint128::from(3333u128),
-                })
-                .unwrap(),
-                funds: vec![]
-            })])
-            .add_attributes(vec![
-                attr("action", "claim"),
-                attr("claim_amount", "3333"),
-                attr("returned_refunded_amount", "0")
-            ])
+        PhaseResponse {
+            phase: Phase::NotStarted,
+            time_to_next_phase: Some(100),
+            paused: false,
+            pause_reason: None,
+        }
     );
 
-    // check query_investor
-    let env = mock_env();
-    let msg = QueryMsg::Investor {
-        wallet: "USER1".to_string(),
-    };
-    let res: InvestorResponse =
-        from_binary(&query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+    let res: PhaseResponse =
+        from_binary(&query(deps.as_ref(), env_at(1000), QueryMsg::Phase {}).unwrap()).unwrap();
     assert_eq!(
         res,
-        InvestorResponse {
-            total_deposited: Uint128::from(10000u128),
-            allocation: Uint128::from(10000u128),
-            refunded: Uint128::from(1000u128),
-            is_refunded: true,
-            deposit_history: vec![DepositInfo {
-                date: env.block.time,
-                amount: Uint128::from(10000u128),
-            }]
+        PhaseResponse {
+            phase: Phase::DepositOpen,
+            time_to_next_phase: Some(DEPOSIT_PERIOD - 1000),
+            paused: false,
+            pause_reason: None,
         }
-    )
+    );
+
+    let env = env_at(DEPOSIT_PERIOD);
+    let res: PhaseResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::Phase {}).unwrap()).unwrap();
+    assert_eq!(
+        res,
+        PhaseResponse {
+            phase: Phase::Allocating,
+            time_to_next_phase: None,
+            paused: false,
+            pause_reason: None,
+        }
+    );
+
+    // only the admin can move on to claiming
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("user1", &[]),
+        EnableWithdraw {
+            check_solvency: None,
+        },
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("admin1", &[]),
+        EnableWithdraw {
+            check_solvency: None,
+        },
+    )
+    .unwrap();
+    let res: PhaseResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::Phase {}).unwrap()).unwrap();
+    assert_eq!(
+        res,
+        PhaseResponse {
+            phase: Phase::Claiming,
+            time_to_next_phase: Some(LOCK_TIME + VESTING_TIME),
+            paused: false,
+            pause_reason: None,
+        }
+    );
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("admin1", &[]),
+        EnableWithdraw {
+            check_solvency: None,
+        },
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::EnableWithdrawNotOpen {});
+
+    let env = env_at(DEPOSIT_PERIOD + LOCK_TIME + VESTING_TIME);
+    let res: PhaseResponse =
+        from_binary(&query(deps.as_ref(), env, QueryMsg::Phase {}).unwrap()).unwrap();
+    assert_eq!(
+        res,
+        PhaseResponse {
+            phase: Phase::Closed,
+            time_to_next_phase: None,
+            paused: false,
+            pause_reason: None,
+        }
+    );
+}
+
+#[test]
+fn test_claim() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+    let info = mock_info("creator", &[]);
+    let _res = instantiate(deps.as_mut(), env.clone(), info, init_msg(&env)).unwrap();
+
+    //deposit
+    let deposit_msg = Deposit {
+        cap: None,
+        proof: None,
+    };
+    let info = mock_info("user1", &[coin(10000, "uusd")]);
+    let _res = execute(deps.as_mut(), env.clone(), info, deposit_msg).unwrap();
+
+    //update is refused while deposits are open
+    let update_msg = Update(vec![UserUpdateData {
+        user_addr: "user1".to_string(),
+        allocation: Uint128::from(10000u128),
+        refunded: Uint128::from(1000u128),
+        schedule: None,
+        clear_schedule: None,
+    }]);
+    let info = mock_info("admin1", &[]);
+    let res = execute(deps.as_mut(), env, info.clone(), update_msg.clone()).unwrap_err();
+    assert_eq!(res, ContractError::AllocationNotOpen {});
+
+    //update
+    let env = env_at(DEPOSIT_PERIOD);
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), update_msg).unwrap();
+    assert_eq!(
+        res,
+        Response::new()
+            .add_messages(vec![CosmosMsg::Bank(BankMsg::Send {
+                to_address: "team_wallet".to_string(),
+                amount: vec![coin(9000, "uusd")]
+            })])
+            .add_attributes(vec![
+                attr("action", "update"),
+                attr("transfer_amount", "9000uusd"),
+                attr("clawback_amount", "0"),
+                attr("new_users", "user1"),
+                attr("amended_users", ""),
+            ])
+    );
+
+    //claim is refused until withdraw is enabled
+    let claim_msg = Claim {
+        investor: None,
+        recipient: None,
+    };
+    let user = mock_info("user1", &[]);
+    let res = execute(deps.as_mut(), env.clone(), user.clone(), claim_msg.clone()).unwrap_err();
+    assert_eq!(res, ContractError::ClaimNotStarted {});
+
+    //can_withdraw
+    let _res = execute(
+        deps.as_mut(),
+        env,
+        info,
+        EnableWithdraw {
+            check_solvency: None,
+        },
+    )
+    .unwrap();
+
+    //claim
+    let env = env_at(DEPOSIT_PERIOD + LOCK_TIME + 2592000); //30 days of vesting
+    let res = execute(deps.as_mut(), env, user.clone(), claim_msg.clone()).unwrap();
+    assert_eq!(
+        res,
+        Response::new()
+            .add_messages(vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "user1".to_string(),
+                    amount: vec![coin(1000u128, "uusd")]
+                }),
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: "sayve_token".to_string(),
+                    msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                        recipient: "user1".to_string(),
+                        amount: Uint128::from(3333u128),
+                    })
+                    .unwrap(),
+                    funds: vec![]
+                }),
+            ])
+            .add_attributes(vec![
+                attr("action", "claim"),
+                attr("claim_amount", "3333"),
+                attr("returned_refunded_amount", "1000"),
+            ])
+    );
+
+    let env = env_at(DEPOSIT_PERIOD + LOCK_TIME + 2592000 + 86400); //1 day later.
+    let res = execute(deps.as_mut(), env, user, claim_msg).unwrap();
+    assert_eq!(
+        res,
+        Response::new()
+            .add_messages(vec![CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "sayve_token".to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: "user1".to_string(),
+                    amount: Uint128::from(111u128),
+                })
+                .unwrap(),
+                funds: vec![]
+            })])
+            .add_attributes(vec![
+                attr("action", "claim"),
+                attr("claim_amount", "111"),
+                attr("returned_refunded_amount", "0")
+            ])
+    );
+
+    // check query_investor
+    let env = mock_env();
+    let msg = QueryMsg::Investor {
+        wallet: "user1".to_string(),
+    };
+    let res: InvestorResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+    assert_eq!(
+        res,
+        InvestorResponse {
+            total_deposited: Uint128::from(10000u128),
+            allocation: Uint128::from(10000u128),
+            refunded: Uint128::from(1000u128),
+            is_refunded: true,
+            deposit_history: vec![DepositInfo {
+                date: env.block.time,
+                amount: Uint128::from(10000u128),
+            }],
+            deposits: vec![DenomDeposit {
+                denom: Denom::Native("uusd".to_string()),
+                amount: Uint128::from(10000u128),
+                normalized: Uint128::from(10000u128),
+            }],
+            vesting_schedule: VestingSchedule {
+                start_time: env.block.time.seconds() + DEPOSIT_PERIOD,
+                lock_time: LOCK_TIME,
+                vesting_time: VESTING_TIME,
+                tge_unlock_bps: 0,
+                schedule_type: ScheduleType::Linear,
+            },
+        }
+    )
+}
+
+fn update_msg(user: &str, allocation: u128, refunded: u128) -> crate::msg::ExecuteMsg {
+    Update(vec![UserUpdateData {
+        user_addr: user.to_string(),
+        allocation: Uint128::from(allocation),
+        refunded: Uint128::from(refunded),
+        schedule: None,
+        clear_schedule: None,
+    }])
+}
+
+#[test]
+fn test_update_idempotent() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+    let _res = instantiate(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("creator", &[]),
+        init_msg(&env),
+    )
+    .unwrap();
+    let deposit_msg = Deposit {
+        cap: None,
+        proof: None,
+    };
+    let info = mock_info("user1", &[coin(10000, "uusd")]);
+    let _res = execute(deps.as_mut(), env.clone(), info, deposit_msg.clone()).unwrap();
+    let info = mock_info("user2", &[coin(5000, "uusd")]);
+    let _res = execute(deps.as_mut(), env, info, deposit_msg).unwrap();
+
+    let env = env_at(DEPOSIT_PERIOD);
+    let admin = mock_info("admin1", &[]);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        update_msg("user1", 10000, 1000),
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 1);
+
+    // re-submitting the same batch moves nothing
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        update_msg("user1", 10000, 1000),
+    )
+    .unwrap();
+    assert_eq!(
+        res,
+        Response::new().add_attributes(vec![
+            attr("action", "update"),
+            attr("transfer_amount", "0"),
+            attr("clawback_amount", "0"),
+            attr("new_users", ""),
+            attr("amended_users", "user1"),
+        ])
+    );
+
+    // a bigger refund claws 2000 back from the next team transfers
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        update_msg("user1", 8000, 3000),
+    )
+    .unwrap();
+    assert_eq!(
+        res,
+        Response::new().add_attributes(vec![
+            attr("action", "update"),
+            attr("transfer_amount", "0"),
+            attr("clawback_amount", "2000uusd"),
+            attr("new_users", ""),
+            attr("amended_users", "user1"),
+        ])
+    );
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        update_msg("user2", 5000, 0),
+    )
+    .unwrap();
+    assert_eq!(
+        res,
+        Response::new()
+            .add_message(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "team_wallet".to_string(),
+                amount: vec![coin(3000, "uusd")]
+            }))
+            .add_attributes(vec![
+                attr("action", "update"),
+                attr("transfer_amount", "3000uusd"),
+                attr("clawback_amount", "0"),
+                attr("new_users", "user2"),
+                attr("amended_users", ""),
+            ])
+    );
+
+    // a smaller refund only sends the difference
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        update_msg("user1", 9500, 500),
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: "team_wallet".to_string(),
+            amount: vec![coin(2500, "uusd")]
+        })
+    );
+
+    // after the refund is returned, a raised refund only pays the difference
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        EnableWithdraw {
+            check_solvency: None,
+        },
+    )
+    .unwrap();
+    let user = mock_info("user1", &[]);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        user.clone(),
+        Claim {
+            investor: None,
+            recipient: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: "user1".to_string(),
+            amount: vec![coin(500, "uusd")]
+        })
+    );
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin,
+        update_msg("user1", 9000, 1000),
+    )
+    .unwrap();
+    let res = execute(
+        deps.as_mut(),
+        env,
+        user,
+        Claim {
+            investor: None,
+            recipient: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: "user1".to_string(),
+            amount: vec![coin(500, "uusd")]
+        })
+    );
+}
+
+#[test]
+fn test_finalize() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+    let _res = instantiate(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("creator", &[]),
+        init_msg(&env),
+    )
+    .unwrap();
+
+    let deposit_msg = Deposit {
+        cap: None,
+        proof: None,
+    };
+    let info = mock_info("user1", &[coin(30000, "uusd")]);
+    let _res = execute(deps.as_mut(), env.clone(), info, deposit_msg.clone()).unwrap();
+    let info = mock_info("user2", &[coin(10000, "uusd")]);
+    let _res = execute(deps.as_mut(), env.clone(), info, deposit_msg).unwrap();
+
+    // 40000 tokens at 0.5 uusd only take 20000 of the 40000 deposited
+    let finalize_msg = Finalize {
+        token_price: Decimal::percent(50),
+        tokens_for_sale: Uint128::from(40000u128),
+    };
+    let admin = mock_info("admin1", &[]);
+    let res = execute(deps.as_mut(), env, admin.clone(), finalize_msg.clone()).unwrap_err();
+    assert_eq!(res, ContractError::FinalizeNotOpen {});
+
+    let env = env_at(DEPOSIT_PERIOD);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        finalize_msg.clone(),
+    )
+    .unwrap();
+    assert_eq!(
+        res,
+        Response::new()
+            .add_message(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "team_wallet".to_string(),
+                amount: vec![coin(20000, "uusd")]
+            }))
+            .add_attributes(vec![
+                attr("action", "finalize"),
+                attr("token_price", "0.5"),
+                attr("tokens_for_sale", "40000"),
+                attr("total_deposited", "40000"),
+                attr("transfer_amount", "20000uusd"),
+            ])
+    );
+    let res = execute(deps.as_mut(), env.clone(), admin.clone(), finalize_msg).unwrap_err();
+    assert_eq!(res, ContractError::AlreadyFinalized {});
+
+    let update_msg = Update(vec![UserUpdateData {
+        user_addr: "user1".to_string(),
+        allocation: Uint128::from(10000u128),
+        refunded: Uint128::zero(),
+        schedule: None,
+        clear_schedule: None,
+    }]);
+    let res = execute(deps.as_mut(), env.clone(), admin.clone(), update_msg).unwrap_err();
+    assert_eq!(res, ContractError::SaleFinalized {});
+
+    let msg = QueryMsg::Investor {
+        wallet: "user1".to_string(),
+    };
+    let res: InvestorResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+    assert_eq!(res.allocation, Uint128::from(30000u128));
+    assert_eq!(res.refunded, Uint128::from(15000u128));
+
+    let _res = execute(
+        deps.as_mut(),
+        env,
+        admin,
+        EnableWithdraw {
+            check_solvency: None,
+        },
+    )
+    .unwrap();
+
+    let env = env_at(DEPOSIT_PERIOD + LOCK_TIME + VESTING_TIME);
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("user2", &[]),
+        Claim {
+            investor: None,
+            recipient: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        res,
+        Response::new()
+            .add_messages(vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "user2".to_string(),
+                    amount: vec![coin(5000u128, "uusd")]
+                }),
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: "sayve_token".to_string(),
+                    msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                        recipient: "user2".to_string(),
+                        amount: Uint128::from(10000u128),
+                    })
+                    .unwrap(),
+                    funds: vec![]
+                }),
+            ])
+            .add_attributes(vec![
+                attr("action", "claim"),
+                attr("claim_amount", "10000"),
+                attr("returned_refunded_amount", "5000"),
+            ])
+    );
+}
+
+#[test]
+fn test_vesting_schedule() {
+    let schedule = VestingSchedule {
+        start_time: 1000,
+        lock_time: 100,
+        vesting_time: 1000,
+        tge_unlock_bps: 1500,
+        schedule_type: ScheduleType::Linear,
+    };
+    let allocation = Uint128::from(10000u128);
+    assert_eq!(schedule.vested_amount(allocation, 999), Uint128::zero());
+    assert_eq!(
+        schedule.vested_amount(allocation, 1000),
+        Uint128::from(1500u128)
+    );
+    assert_eq!(
+        schedule.vested_amount(allocation, 1099),
+        Uint128::from(1500u128)
+    );
+    assert_eq!(
+        schedule.vested_amount(allocation, 1100),
+        Uint128::from(1500u128)
+    );
+    assert_eq!(
+        schedule.vested_amount(allocation, 1600),
+        Uint128::from(5750u128)
+    );
+    assert_eq!(schedule.vested_amount(allocation, 2100), allocation);
+    assert_eq!(schedule.vested_amount(allocation, 5000), allocation);
+    assert_eq!(
+        schedule.next_unlock(allocation, 0),
+        Some((1000, Uint128::from(1500u128)))
+    );
+    assert_eq!(
+        schedule.next_unlock(allocation, 1000),
+        Some((1101, Uint128::from(8u128)))
+    );
+    assert_eq!(
+        schedule.next_unlock(allocation, 1600),
+        Some((1601, Uint128::from(8u128)))
+    );
+    assert_eq!(schedule.next_unlock(allocation, 2100), None);
+}
+
+#[test]
+fn test_periodic_vesting_schedule() {
+    // 20% at TGE, then 4 quarterly unlocks of 20% after a 100 second cliff
+    let schedule = VestingSchedule {
+        start_time: 1000,
+        lock_time: 100,
+        vesting_time: 0,
+        tge_unlock_bps: 2000,
+        schedule_type: ScheduleType::Periodic {
+            interval: 300,
+            count: 4,
+        },
+    };
+    schedule.validate().unwrap();
+    let allocation = Uint128::from(10000u128);
+    assert_eq!(schedule.end_time(), 2300);
+    assert_eq!(schedule.vested_amount(allocation, 999), Uint128::zero());
+    assert_eq!(
+        schedule.vested_amount(allocation, 1399),
+        Uint128::from(2000u128)
+    );
+    assert_eq!(
+        schedule.vested_amount(allocation, 1400),
+        Uint128::from(4000u128)
+    );
+    assert_eq!(
+        schedule.vested_amount(allocation, 2299),
+        Uint128::from(8000u128)
+    );
+    assert_eq!(schedule.vested_amount(allocation, 2300), allocation);
+    assert_eq!(
+        schedule.next_unlock(allocation, 1500),
+        Some((1700, Uint128::from(2000u128)))
+    );
+    assert_eq!(schedule.next_unlock(allocation, 2300), None);
+
+    let invalid = VestingSchedule {
+        schedule_type: ScheduleType::Periodic {
+            interval: 0,
+            count: 4,
+        },
+        ..schedule
+    };
+    assert_eq!(
+        invalid.validate().unwrap_err(),
+        ContractError::InvalidPeriodicSchedule {}
+    );
+}
+
+#[test]
+fn test_custom_vesting_schedule() {
+    let schedule = VestingSchedule {
+        start_time: 1000,
+        lock_time: 0,
+        vesting_time: 0,
+        tge_unlock_bps: 1000,
+        schedule_type: ScheduleType::Custom {
+            checkpoints: vec![
+                Checkpoint {
+                    time: 2000,
+                    bps: 3000,
+                },
+                Checkpoint {
+                    time: 5000,
+                    bps: 6000,
+                },
+            ],
+        },
+    };
+    schedule.validate().unwrap();
+    let allocation = Uint128::from(10000u128);
+    assert_eq!(schedule.end_time(), 5000);
+    assert_eq!(
+        schedule.vested_amount(allocation, 1000),
+        Uint128::from(1000u128)
+    );
+    assert_eq!(
+        schedule.vested_amount(allocation, 2000),
+        Uint128::from(4000u128)
+    );
+    assert_eq!(schedule.vested_amount(allocation, 5000), allocation);
+    assert_eq!(
+        schedule.next_unlock(allocation, 1000),
+        Some((2000, Uint128::from(3000u128)))
+    );
+
+    // an allocation that does not split evenly still vests completely
+    let uneven = VestingSchedule {
+        schedule_type: ScheduleType::Custom {
+            checkpoints: vec![
+                Checkpoint {
+                    time: 2000,
+                    bps: 4500,
+                },
+                Checkpoint {
+                    time: 5000,
+                    bps: 4500,
+                },
+            ],
+        },
+        ..schedule.clone()
+    };
+    uneven.validate().unwrap();
+    let allocation = Uint128::from(1_000_001u128);
+    assert_eq!(
+        uneven.vested_amount(allocation, 2000),
+        Uint128::from(550_000u128)
+    );
+    assert_eq!(
+        uneven.next_unlock(allocation, 2000),
+        Some((5000, Uint128::from(450_001u128)))
+    );
+    assert_eq!(uneven.vested_amount(allocation, 5000), allocation);
+    assert_eq!(uneven.next_unlock(allocation, 5000), None);
+
+    let invalid = VestingSchedule {
+        tge_unlock_bps: 0,
+        ..schedule
+    };
+    assert_eq!(
+        invalid.validate().unwrap_err(),
+        ContractError::IncompleteCheckpoints { total_bps: 9000 }
+    );
+}
+
+#[test]
+fn test_claim_tge_unlock() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+    let mut init = init_msg(&env);
+    init.tge_unlock_bps = 1000;
+    let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), init).unwrap();
+
+    let info = mock_info("user1", &[coin(10000, "uusd")]);
+    let deposit_msg = Deposit {
+        cap: None,
+        proof: None,
+    };
+    let _res = execute(deps.as_mut(), env, info, deposit_msg).unwrap();
+
+    let admin = mock_info("admin1", &[]);
+    let user = mock_info("user1", &[]);
+    let env = env_at(DEPOSIT_PERIOD);
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        update_msg("user1", 10000, 0),
+    )
+    .unwrap();
+    let _res = execute(
+        deps.as_mut(),
+        env,
+        admin,
+        EnableWithdraw {
+            check_solvency: None,
+        },
+    )
+    .unwrap();
+
+    // TGE is at the end of the deposit window
+    let env = env_at(DEPOSIT_PERIOD + 10);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        user.clone(),
+        Claim {
+            investor: None,
+            recipient: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "claim"),
+            attr("claim_amount", "1000"),
+            attr("returned_refunded_amount", "0"),
+        ]
+    );
+    // the TGE share is out, nothing more unlocks before the cliff ends
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        user.clone(),
+        Claim {
+            investor: None,
+            recipient: None,
+        },
+    )
+    .unwrap_err();
+    assert_eq!(
+        res,
+        ContractError::StillLocked {
+            seconds_left: LOCK_TIME - 10 + 864,
+        }
+    );
+    let res: ClaimableAmountResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env,
+            QueryMsg::ClaimableAmount {
+                wallet: "user1".to_string(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res,
+        ClaimableAmountResponse {
+            lock_time_left: LOCK_TIME - 10,
+            tokens_allocated: "10000".to_string(),
+            token_avaiable_to_claim: "0".to_string(),
+            returned_ust: "0".to_string(),
+            next_unlock_time: Some(
+                mock_env().block.time.seconds() + DEPOSIT_PERIOD + LOCK_TIME + 864
+            ),
+            next_unlock_amount: Uint128::from(1u128),
+        }
+    );
+
+    // half way through the linear part
+    let env = env_at(DEPOSIT_PERIOD + LOCK_TIME + VESTING_TIME / 2);
+    let res = execute(
+        deps.as_mut(),
+        env,
+        user.clone(),
+        Claim {
+            investor: None,
+            recipient: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "claim"),
+            attr("claim_amount", "4500"),
+            attr("returned_refunded_amount", "0"),
+        ]
+    );
+
+    // a wallet that claimed everything gets an error instead of an empty claim
+    let env = env_at(DEPOSIT_PERIOD + LOCK_TIME + VESTING_TIME);
+    let claim_msg = Claim {
+        investor: None,
+        recipient: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), user.clone(), claim_msg.clone()).unwrap();
+    let res = execute(deps.as_mut(), env, user, claim_msg).unwrap_err();
+    assert_eq!(res, ContractError::NothingToClaim {});
+}
+
+#[test]
+fn test_user_schedule() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+    let _res = instantiate(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("creator", &[]),
+        init_msg(&env),
+    )
+    .unwrap();
+    let deposit_msg = Deposit {
+        cap: None,
+        proof: None,
+    };
+    let info = mock_info("user1", &[coin(10000, "uusd")]);
+    let _res = execute(deps.as_mut(), env.clone(), info, deposit_msg.clone()).unwrap();
+    let info = mock_info("user2", &[coin(10000, "uusd")]);
+    let _res = execute(deps.as_mut(), env, info, deposit_msg).unwrap();
+
+    // user2 gets half at TGE and no cliff
+    let tge = mock_env().block.time.seconds() + DEPOSIT_PERIOD;
+    let strategic_schedule = VestingSchedule {
+        start_time: tge,
+        lock_time: 0,
+        vesting_time: VESTING_TIME,
+        tge_unlock_bps: 5000,
+        schedule_type: ScheduleType::Linear,
+    };
+    let env = env_at(DEPOSIT_PERIOD);
+    let admin = mock_info("admin1", &[]);
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        Update(vec![
+            UserUpdateData {
+                user_addr: "user1".to_string(),
+                allocation: Uint128::from(10000u128),
+                refunded: Uint128::zero(),
+                schedule: None,
+                clear_schedule: None,
+            },
+            UserUpdateData {
+                user_addr: "user2".to_string(),
+                allocation: Uint128::from(10000u128),
+                refunded: Uint128::zero(),
+                schedule: Some(strategic_schedule.clone()),
+                clear_schedule: None,
+            },
+        ]),
+    )
+    .unwrap();
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin,
+        EnableWithdraw {
+            check_solvency: None,
+        },
+    )
+    .unwrap();
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("user1", &[]),
+        Claim {
+            investor: None,
+            recipient: None,
+        },
+    )
+    .unwrap_err();
+    assert_eq!(
+        res,
+        ContractError::StillLocked {
+            seconds_left: LOCK_TIME + 778,
+        }
+    );
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("user2", &[]),
+        Claim {
+            investor: None,
+            recipient: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "claim"),
+            attr("claim_amount", "5000"),
+            attr("returned_refunded_amount", "0"),
+        ]
+    );
+
+    let msg = QueryMsg::Investor {
+        wallet: "user2".to_string(),
+    };
+    let res: InvestorResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+    assert_eq!(res.vesting_schedule, strategic_schedule);
+
+    let msg = QueryMsg::ClaimableAmount {
+        wallet: "user2".to_string(),
+    };
+    let res: ClaimableAmountResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env_at(DEPOSIT_PERIOD + VESTING_TIME / 2),
+            msg,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.token_avaiable_to_claim, "2500".to_string());
+    assert_eq!(res.lock_time_left, 0);
+
+    // a re-sent batch without the schedule keeps user2's
+    let admin = mock_info("admin1", &[]);
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        update_msg("user2", 10000, 0),
+    )
+    .unwrap();
+    let msg = QueryMsg::Investor {
+        wallet: "user2".to_string(),
+    };
+    let res: InvestorResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+    assert_eq!(res.vesting_schedule, strategic_schedule);
+
+    // once claiming started the override follows the UpdateSchedule rules
+    let schedule_update = |schedule: Option<VestingSchedule>, clear_schedule: Option<bool>| {
+        Update(vec![UserUpdateData {
+            user_addr: "user2".to_string(),
+            allocation: Uint128::from(10000u128),
+            refunded: Uint128::zero(),
+            schedule,
+            clear_schedule,
+        }])
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        schedule_update(None, Some(true)),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::VestedAmountReduced {});
+    let earlier_schedule = VestingSchedule {
+        start_time: tge - 100,
+        ..strategic_schedule.clone()
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        schedule_update(Some(earlier_schedule), None),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::ScheduleMovedEarlier {});
+    let res = execute(
+        deps.as_mut(),
+        env,
+        admin,
+        schedule_update(Some(strategic_schedule), Some(true)),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::ConflictingSchedule {});
+}
+
+fn cw20_deposit(sender: &str, amount: u128) -> crate::msg::ExecuteMsg {
+    Receive(Cw20ReceiveMsg {
+        sender: sender.to_string(),
+        amount: Uint128::from(amount),
+        msg: to_binary(&Cw20HookMsg::Deposit {
+            cap: None,
+            proof: None,
+        })
+        .unwrap(),
+    })
+}
+
+#[test]
+fn test_cw20_deposit() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+    let mut init = init_msg(&env);
+    init.stable_token = Some("stable_token".to_string());
+    init.hard_cap = Uint128::from(15000u128);
+    let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), init).unwrap();
+
+    // native coins are refused once the sale raises in a CW20, unless whitelisted
+    let info = mock_info("user1", &[coin(10000, "uusd")]);
+    let deposit_msg = Deposit {
+        cap: None,
+        proof: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), info, deposit_msg).unwrap_err();
+    assert_eq!(
+        res,
+        ContractError::DenomNotAccepted {
+            denom: "uusd".to_string(),
+        }
+    );
+
+    // only the configured token can credit a deposit
+    let info = mock_info("other_token", &[]);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info,
+        cw20_deposit("user1", 10000),
+    )
+    .unwrap_err();
+    assert_eq!(
+        res,
+        ContractError::DenomNotAccepted {
+            denom: "other_token".to_string(),
+        }
+    );
+
+    let info = mock_info("stable_token", &[]);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        cw20_deposit("user1", 10000),
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![attr("action", "deposit"), attr("amount", "10000")]
+    );
+
+    // the part over the hard cap goes back as a CW20 transfer
+    let res = execute(deps.as_mut(), env, info, cw20_deposit("user2", 10000)).unwrap();
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "stable_token".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: "user2".to_string(),
+                amount: Uint128::from(5000u128),
+            })
+            .unwrap(),
+            funds: vec![],
+        })
+    );
+
+    // team sweep and refunds are paid in the same token
+    let admin = mock_info("admin1", &[]);
+    let env = env_at(DEPOSIT_PERIOD);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        update_msg("user1", 8000, 2000),
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "stable_token".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: "team_wallet".to_string(),
+                amount: Uint128::from(8000u128),
+            })
+            .unwrap(),
+            funds: vec![],
+        })
+    );
+    let _res = execute(
+        deps.as_mut(),
+        env,
+        admin,
+        EnableWithdraw {
+            check_solvency: None,
+        },
+    )
+    .unwrap();
+
+    let env = env_at(DEPOSIT_PERIOD + 10);
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("user1", &[]),
+        Claim {
+            investor: None,
+            recipient: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "stable_token".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: "user1".to_string(),
+                amount: Uint128::from(2000u128),
+            })
+            .unwrap(),
+            funds: vec![],
+        })
+    );
+}
+
+#[test]
+fn test_multi_denom_deposit() {
+    let mut deps = mock_dependencies_with_querier(&[]);
+    deps.querier
+        .with_oracle_rate("oracle", "atom_token", Decimal::from_ratio(10u128, 1u128));
+    let env = mock_env();
+    let mut init = init_msg(&env);
+    init.hard_cap = Uint128::from(25000u128);
+    let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), init).unwrap();
+
+    let admin = mock_info("admin1", &[]);
+    let luna_msg = UpdateAcceptedDenom {
+        denom: Denom::Native("uluna".to_string()),
+        price_source: Some(PriceSource::Fixed {
+            rate: Decimal::from_ratio(2u128, 1u128),
+        }),
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("user1", &[]),
+        luna_msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+    let _res = execute(deps.as_mut(), env.clone(), admin.clone(), luna_msg).unwrap();
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        UpdateAcceptedDenom {
+            denom: Denom::Cw20(Addr::unchecked("atom_token")),
+            price_source: Some(PriceSource::Oracle {
+                contract_addr: "oracle".to_string(),
+            }),
+        },
+    )
+    .unwrap();
+
+    let res: AcceptedDenomsResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::AcceptedDenoms {}).unwrap())
+            .unwrap();
+    assert_eq!(
+        res.denoms
+            .iter()
+            .map(|d| (d.denom.clone(), d.rate))
+            .collect::<Vec<_>>(),
+        vec![
+            (Denom::Native("uusd".to_string()), Decimal::one()),
+            (
+                Denom::Cw20(Addr::unchecked("atom_token")),
+                Decimal::from_ratio(10u128, 1u128)
+            ),
+            (
+                Denom::Native("uluna".to_string()),
+                Decimal::from_ratio(2u128, 1u128)
+            ),
+        ]
+    );
+
+    let deposit_msg = Deposit {
+        cap: None,
+        proof: None,
+    };
+    let info = mock_info("user1", &[coin(10000, "uusd"), coin(1000, "ukrw")]);
+    let res = execute(deps.as_mut(), env.clone(), info, deposit_msg.clone()).unwrap_err();
+    assert_eq!(
+        res,
+        ContractError::DenomNotAccepted {
+            denom: "ukrw".to_string(),
+        }
+    );
+
+    // both coins are recorded at their stable value
+    let info = mock_info("user1", &[coin(10000, "uusd"), coin(5000, "uluna")]);
+    let res = execute(deps.as_mut(), env.clone(), info, deposit_msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![attr("action", "deposit"), attr("amount", "20000")]
+    );
+
+    // only 500 atom still fit under the hard cap, the rest goes back in atom
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("atom_token", &[]),
+        cw20_deposit("user2", 1000),
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "deposit"),
+            attr("amount", "5000"),
+            attr("refund_amount", "5000"),
+        ]
+    );
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "atom_token".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: "user2".to_string(),
+                amount: Uint128::from(500u128),
+            })
+            .unwrap(),
+            funds: vec![],
+        })
+    );
+
+    // each denom is swept in the share the wallet deposited it
+    let env = env_at(DEPOSIT_PERIOD);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        Update(vec![
+            UserUpdateData {
+                user_addr: "user1".to_string(),
+                allocation: Uint128::from(10000u128),
+                refunded: Uint128::from(10000u128),
+                schedule: None,
+                clear_schedule: None,
+            },
+            UserUpdateData {
+                user_addr: "user2".to_string(),
+                allocation: Uint128::from(5000u128),
+                refunded: Uint128::zero(),
+                schedule: None,
+                clear_schedule: None,
+            },
+        ]),
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes[1],
+        attr("transfer_amount", "2500uluna,5000uusd,500atom_token")
+    );
+    assert_eq!(res.messages.len(), 3);
+    let _res = execute(
+        deps.as_mut(),
+        env,
+        admin,
+        EnableWithdraw {
+            check_solvency: None,
+        },
+    )
+    .unwrap();
+
+    let env = env_at(DEPOSIT_PERIOD + 10);
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("user1", &[]),
+        Claim {
+            investor: None,
+            recipient: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages
+            .iter()
+            .map(|m| m.msg.clone())
+            .collect::<Vec<CosmosMsg>>(),
+        vec![
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "user1".to_string(),
+                amount: vec![coin(2500, "uluna")],
+            }),
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "user1".to_string(),
+                amount: vec![coin(5000, "uusd")],
+            }),
+        ]
+    );
+}
+
+#[test]
+fn test_claim_and_stake() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+    let _res = instantiate(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("creator", &[]),
+        init_msg(&env),
+    )
+    .unwrap();
+    let info = mock_info("user1", &[coin(10000, "uusd")]);
+    let deposit_msg = Deposit {
+        cap: None,
+        proof: None,
+    };
+    let _res = execute(deps.as_mut(), env, info, deposit_msg).unwrap();
+
+    let admin = mock_info("admin1", &[]);
+    let user = mock_info("user1", &[]);
+    let env = env_at(DEPOSIT_PERIOD);
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        update_msg("user1", 10000, 0),
+    )
+    .unwrap();
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        EnableWithdraw {
+            check_solvency: None,
+        },
+    )
+    .unwrap();
+
+    let env = env_at(DEPOSIT_PERIOD + LOCK_TIME + VESTING_TIME / 2);
+    let res = execute(deps.as_mut(), env.clone(), user.clone(), ClaimAndStake {}).unwrap_err();
+    assert_eq!(res, ContractError::StakingNotConfigured {});
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin,
+        UpdateStakingContract {
+            staking_contract: Some("gov".to_string()),
+        },
+    )
+    .unwrap();
+
+    let res = execute(deps.as_mut(), env.clone(), user.clone(), ClaimAndStake {}).unwrap();
+    assert_eq!(
+        res,
+        Response::new()
+            .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "sayve_token".to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Send {
+                    contract: "gov".to_string(),
+                    amount: Uint128::from(5000u128),
+                    msg: to_binary(&Cw20HookMsg::StakeVotingTokens {
+                        beneficiary: Some("user1".to_string()),
+                    })
+                    .unwrap(),
+                })
+                .unwrap(),
+                funds: vec![],
+            }))
+            .add_attributes(vec![
+                attr("action", "claim_and_stake"),
+                attr("claim_amount", "5000"),
+                attr("returned_refunded_amount", "0"),
+            ])
+    );
+
+    // staked tokens count as released for a later plain claim
+    let env = env_at(DEPOSIT_PERIOD + LOCK_TIME + VESTING_TIME);
+    let res = execute(
+        deps.as_mut(),
+        env,
+        user,
+        Claim {
+            investor: None,
+            recipient: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(res.attributes[1], attr("claim_amount", "5000"));
+}
+
+#[test]
+fn test_distribute() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+    let _res = instantiate(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("creator", &[]),
+        init_msg(&env),
+    )
+    .unwrap();
+    let deposit_msg = Deposit {
+        cap: None,
+        proof: None,
+    };
+    for user in ["user1", "user2", "user3"] {
+        let info = mock_info(user, &[coin(10000, "uusd")]);
+        let _res = execute(deps.as_mut(), env.clone(), info, deposit_msg.clone()).unwrap();
+    }
+
+    let admin = mock_info("admin1", &[]);
+    let env = env_at(DEPOSIT_PERIOD);
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        Update(vec![
+            UserUpdateData {
+                user_addr: "user1".to_string(),
+                allocation: Uint128::from(10000u128),
+                refunded: Uint128::from(1000u128),
+                schedule: None,
+                clear_schedule: None,
+            },
+            UserUpdateData {
+                user_addr: "user2".to_string(),
+                allocation: Uint128::from(10000u128),
+                refunded: Uint128::zero(),
+                schedule: None,
+                clear_schedule: None,
+            },
+            UserUpdateData {
+                user_addr: "user3".to_string(),
+                allocation: Uint128::from(10000u128),
+                refunded: Uint128::zero(),
+                schedule: None,
+                clear_schedule: None,
+            },
+        ]),
+    )
+    .unwrap();
+
+    let distribute_msg = Distribute {
+        start_after: None,
+        limit: Some(2),
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        distribute_msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::ClaimNotStarted {});
+    let _res = execute(
+        deps.as_mut(),
+        env,
+        admin.clone(),
+        EnableWithdraw {
+            check_solvency: None,
+        },
+    )
+    .unwrap();
+
+    let env = env_at(DEPOSIT_PERIOD + LOCK_TIME + VESTING_TIME / 2);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("user1", &[]),
+        distribute_msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+
+    // user2 claims by themselves in between
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("user2", &[]),
+        Claim {
+            investor: None,
+            recipient: None,
+        },
+    )
+    .unwrap();
+
+    let res = execute(deps.as_mut(), env.clone(), admin.clone(), distribute_msg).unwrap();
+    assert_eq!(
+        res.messages
+            .iter()
+            .map(|m| m.msg.clone())
+            .collect::<Vec<CosmosMsg>>(),
+        vec![
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "user1".to_string(),
+                amount: vec![coin(1000, "uusd")],
+            }),
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "sayve_token".to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: "user1".to_string(),
+                    amount: Uint128::from(5000u128),
+                })
+                .unwrap(),
+                funds: vec![],
+            }),
+        ]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "distribute"),
+            attr("users", "2"),
+            attr("claim_amount", "5000"),
+            attr("returned_refunded_amount", "1000"),
+            attr("last_user", "user2"),
+        ]
+    );
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin,
+        Distribute {
+            start_after: Some("user2".to_string()),
+            limit: Some(2),
+        },
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(res.attributes[1], attr("users", "1"));
+    assert_eq!(res.attributes[4], attr("last_user", ""));
+
+    // what was pushed is booked like a claim
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("user1", &[]),
+        Claim {
+            investor: None,
+            recipient: None,
+        },
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::StillLocked { seconds_left: 778 });
+}
+
+#[test]
+fn test_distribute_refunding() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+    let mut init = init_msg(&env);
+    init.soft_cap = Uint128::from(50000u128);
+    let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), init).unwrap();
+    let deposit_msg = Deposit {
+        cap: None,
+        proof: None,
+    };
+    for user in ["user1", "user2", "user3"] {
+        let info = mock_info(user, &[coin(10000, "uusd")]);
+        let _res = execute(deps.as_mut(), env.clone(), info, deposit_msg.clone()).unwrap();
+    }
+
+    // user2 takes the refund by themselves in between
+    let env = env_at(DEPOSIT_PERIOD);
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("user2", &[]),
+        Claim {
+            investor: None,
+            recipient: None,
+        },
+    )
+    .unwrap();
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("admin1", &[]),
+        Distribute {
+            start_after: None,
+            limit: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages
+            .iter()
+            .map(|m| m.msg.clone())
+            .collect::<Vec<CosmosMsg>>(),
+        vec![
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "user1".to_string(),
+                amount: vec![coin(10000, "uusd")],
+            }),
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "user3".to_string(),
+                amount: vec![coin(10000, "uusd")],
+            }),
+        ]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "distribute"),
+            attr("users", "3"),
+            attr("claim_amount", "0"),
+            attr("returned_refunded_amount", "20000"),
+            attr("last_user", ""),
+        ]
+    );
+
+    // what was pushed is booked like a claim
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("user1", &[]),
+        Claim {
+            investor: None,
+            recipient: None,
+        },
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::AlreadyRefunded {});
+}
+
+#[test]
+fn test_claim_operator() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+    let _res = instantiate(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("creator", &[]),
+        init_msg(&env),
+    )
+    .unwrap();
+    let info = mock_info("user1", &[coin(10000, "uusd")]);
+    let deposit_msg = Deposit {
+        cap: None,
+        proof: None,
+    };
+    let _res = execute(deps.as_mut(), env, info, deposit_msg).unwrap();
+
+    let admin = mock_info("admin1", &[]);
+    let env = env_at(DEPOSIT_PERIOD);
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        update_msg("user1", 10000, 0),
+    )
+    .unwrap();
+    let _res = execute(
+        deps.as_mut(),
+        env,
+        admin,
+        EnableWithdraw {
+            check_solvency: None,
+        },
+    )
+    .unwrap();
+
+    let env = env_at(DEPOSIT_PERIOD + LOCK_TIME + VESTING_TIME / 2);
+    let operator_claim = Claim {
+        investor: Some("user1".to_string()),
+        recipient: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("operator", &[]),
+        operator_claim.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("user1", &[]),
+        ApproveOperator {
+            operator: "operator".to_string(),
+        },
+    )
+    .unwrap();
+    let res: OperatorsResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::Operators {
+                wallet: "user1".to_string(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res,
+        OperatorsResponse {
+            wallet: "user1".to_string(),
+            operators: vec!["operator".to_string()],
+        }
+    );
+
+    // the operator can not send user1's tokens anywhere else
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("operator", &[]),
+        Claim {
+            investor: Some("user1".to_string()),
+            recipient: Some("operator".to_string()),
+        },
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::OperatorRecipient {});
+
+    // the operator claims for user1, the tokens still go to user1
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("operator", &[]),
+        operator_claim.clone(),
+    )
+    .unwrap();
+    assert_eq!(
+        res,
+        Response::new()
+            .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "sayve_token".to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: "user1".to_string(),
+                    amount: Uint128::from(5000u128),
+                })
+                .unwrap(),
+                funds: vec![],
+            }))
+            .add_attributes(vec![
+                attr("action", "claim"),
+                attr("claim_amount", "5000"),
+                attr("returned_refunded_amount", "0"),
+                attr("investor", "user1"),
+                attr("recipient", "user1"),
+            ])
+    );
+
+    let _res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("user1", &[]),
+        RevokeOperator {
+            operator: "operator".to_string(),
+        },
+    )
+    .unwrap();
+    let env = env_at(DEPOSIT_PERIOD + LOCK_TIME + VESTING_TIME);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("operator", &[]),
+        operator_claim,
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+
+    // the investor sends the rest to another wallet
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("user1", &[]),
+        Claim {
+            investor: None,
+            recipient: Some("cold_wallet".to_string()),
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "sayve_token".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: "cold_wallet".to_string(),
+                amount: Uint128::from(5000u128),
+            })
+            .unwrap(),
+            funds: vec![],
+        })
+    );
+}
+
+#[test]
+fn test_transfer_position() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+    let _res = instantiate(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("creator", &[]),
+        init_msg(&env),
+    )
+    .unwrap();
+    let deposit_msg = Deposit {
+        cap: None,
+        proof: None,
+    };
+    for user in ["user1", "user2"] {
+        let info = mock_info(user, &[coin(10000, "uusd")]);
+        let _res = execute(deps.as_mut(), env.clone(), info, deposit_msg.clone()).unwrap();
+    }
+    let transfer_msg = TransferPosition {
+        to: "user3".to_string(),
+    };
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("user1", &[]),
+        transfer_msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::TransferDuringDeposit {});
+
+    let admin = mock_info("admin1", &[]);
+    let env = env_at(DEPOSIT_PERIOD);
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        Update(vec![
+            UserUpdateData {
+                user_addr: "user1".to_string(),
+                allocation: Uint128::from(10000u128),
+                refunded: Uint128::from(2000u128),
+                schedule: None,
+                clear_schedule: None,
+            },
+            UserUpdateData {
+                user_addr: "user2".to_string(),
+                allocation: Uint128::from(10000u128),
+                refunded: Uint128::zero(),
+                schedule: None,
+                clear_schedule: None,
+            },
+        ]),
+    )
+    .unwrap();
+    let _res = execute(
+        deps.as_mut(),
+        env,
+        admin.clone(),
+        EnableWithdraw {
+            check_solvency: None,
+        },
+    )
+    .unwrap();
+
+    let env = env_at(DEPOSIT_PERIOD + LOCK_TIME + VESTING_TIME / 2);
+    let user1 = mock_info("user1", &[]);
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        user1.clone(),
+        Claim {
+            investor: None,
+            recipient: None,
+        },
+    )
+    .unwrap();
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        user1.clone(),
+        TransferPosition {
+            to: "user2".to_string(),
+        },
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::RecipientHasPosition {});
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        user1.clone(),
+        transfer_msg.clone(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "transfer_position"),
+            attr("from", "user1"),
+            attr("to", "user3"),
+        ]
+    );
+    let res = execute(deps.as_mut(), env.clone(), user1.clone(), transfer_msg).unwrap_err();
+    assert_eq!(res, ContractError::NoPosition {});
+
+    // released and refunded amounts came along, only the newly vested part is paid
+    let env = env_at(DEPOSIT_PERIOD + LOCK_TIME + VESTING_TIME);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("user3", &[]),
+        Claim {
+            investor: None,
+            recipient: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "claim"),
+            attr("claim_amount", "5000"),
+            attr("returned_refunded_amount", "0"),
+        ]
+    );
+
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin,
+        UpdatePositionTransfers { enabled: false },
+    )
+    .unwrap();
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("user2", &[]),
+        TransferPosition {
+            to: "user1".to_string(),
+        },
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::TransfersDisabled {});
+}
+
+#[test]
+fn test_position_nft() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+    let mut init = init_msg(&env);
+    init.position_nft = Some(PositionNftInfo {
+        name: "Sayve prefund position".to_string(),
+        symbol: "SAYVE-P".to_string(),
+    });
+    let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), init).unwrap();
+    let info = mock_info("user1", &[coin(10000, "uusd")]);
+    let deposit_msg = Deposit {
+        cap: None,
+        proof: None,
+    };
+    let _res = execute(deps.as_mut(), env, info, deposit_msg).unwrap();
+
+    // the uploaded allocation mints the token, a re-submission does not
+    let admin = mock_info("admin1", &[]);
+    let env = env_at(DEPOSIT_PERIOD);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        update_msg("user1", 10000, 0),
+    )
+    .unwrap();
+    assert_eq!(res.attributes[5], attr("minted_tokens", "user1"));
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        update_msg("user1", 10000, 0),
+    )
+    .unwrap();
+    assert_eq!(res.attributes[5], attr("minted_tokens", ""));
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin,
+        EnableWithdraw {
+            check_solvency: None,
+        },
+    )
+    .unwrap();
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("user2", &[]),
+        TransferNft {
+            recipient: "buyer".to_string(),
+            token_id: "user1".to_string(),
+        },
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("user1", &[]),
+        Approve {
+            spender: "market".to_string(),
+            token_id: "user1".to_string(),
+            expires: None,
+        },
+    )
+    .unwrap();
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("market", &[]),
+        TransferNft {
+            recipient: "buyer".to_string(),
+            token_id: "user1".to_string(),
+        },
+    )
+    .unwrap();
+
+    let res: OwnerOfResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::OwnerOf {
+                token_id: "user1".to_string(),
+                include_expired: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res,
+        OwnerOfResponse {
+            owner: "buyer".to_string(),
+            approvals: vec![],
+        }
+    );
+    let res: TokensResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::Tokens {
+                owner: "buyer".to_string(),
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.tokens, vec!["user1".to_string()]);
+
+    // the original depositor is no longer paid
+    let env = env_at(DEPOSIT_PERIOD + LOCK_TIME + VESTING_TIME / 2);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("user1", &[]),
+        Claim {
+            investor: None,
+            recipient: None,
+        },
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("buyer", &[]),
+        Claim {
+            investor: Some("user1".to_string()),
+            recipient: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "sayve_token".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: "buyer".to_string(),
+                amount: Uint128::from(5000u128),
+            })
+            .unwrap(),
+            funds: vec![],
+        })
+    );
+
+    let res: NftInfoResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env,
+            QueryMsg::NftInfo {
+                token_id: "user1".to_string(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res,
+        NftInfoResponse {
+            token_uri: None,
+            extension: PositionExtension {
+                investor: "user1".to_string(),
+                allocation: Uint128::from(10000u128),
+                released: Uint128::from(5000u128),
+                refunded: Uint128::zero(),
+                returned_refunded: Uint128::zero(),
+            },
+        }
+    );
+}
+
+#[test]
+fn test_position_nft_finalized() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+    let mut init = init_msg(&env);
+    init.position_nft = Some(PositionNftInfo {
+        name: "Sayve prefund position".to_string(),
+        symbol: "SAYVE-P".to_string(),
+    });
+    let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), init).unwrap();
+    let deposit_msg = Deposit {
+        cap: None,
+        proof: None,
+    };
+    for user in ["user1", "user2", "user3"] {
+        let info = mock_info(user, &[coin(10000, "uusd")]);
+        let _res = execute(deps.as_mut(), env.clone(), info, deposit_msg.clone()).unwrap();
+    }
+
+    let admin = mock_info("admin1", &[]);
+    let env = env_at(DEPOSIT_PERIOD);
+    let mint_msg = MintPositions {
+        start_after: None,
+        limit: Some(2),
+    };
+    let res = execute(deps.as_mut(), env.clone(), admin.clone(), mint_msg.clone()).unwrap_err();
+    assert_eq!(res, ContractError::NotFinalized {});
+
+    // finalizing does not walk the depositors
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        Finalize {
+            token_price: Decimal::one(),
+            tokens_for_sale: Uint128::from(30000u128),
+        },
+    )
+    .unwrap();
+    assert_eq!(res.attributes.len(), 5);
+
+    // the cw721 queries already see every position, minted or not
+    let res: NumTokensResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::NumTokens {}).unwrap()).unwrap();
+    assert_eq!(res.count, 3);
+    let res: OwnerOfResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::OwnerOf {
+                token_id: "user1".to_string(),
+                include_expired: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.owner, "user1".to_string());
+    let res: TokensResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::AllTokens {
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.tokens, vec!["user1", "user2", "user3"]);
+    let res: TokensResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::Tokens {
+                owner: "user3".to_string(),
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.tokens, vec!["user3"]);
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::OwnerOf {
+            token_id: "user4".to_string(),
+            include_expired: None,
+        },
+    )
+    .unwrap_err();
+    assert_eq!(
+        res,
+        ContractError::TokenNotFound {
+            token_id: "user4".to_string(),
+        }
+    );
+
+    // the token is minted on its first cw721 use
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("user2", &[]),
+        TransferNft {
+            recipient: "buyer".to_string(),
+            token_id: "user2".to_string(),
+        },
+    )
+    .unwrap();
+    let res: OwnerOfResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::OwnerOf {
+                token_id: "user2".to_string(),
+                include_expired: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.owner, "buyer".to_string());
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("user1", &[]),
+        mint_msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+    let res = execute(deps.as_mut(), env.clone(), admin.clone(), mint_msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "mint_positions"),
+            attr("users", "2"),
+            attr("minted_tokens", "1"),
+            attr("last_user", "user2"),
+        ]
+    );
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin,
+        MintPositions {
+            start_after: Some("user2".to_string()),
+            limit: Some(2),
+        },
+    )
+    .unwrap();
+    assert_eq!(res.attributes[2], attr("minted_tokens", "1"));
+    assert_eq!(res.attributes[3], attr("last_user", ""));
+
+    let res: NumTokensResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::NumTokens {}).unwrap()).unwrap();
+    assert_eq!(res.count, 3);
+    let res: TokensResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env,
+            QueryMsg::Tokens {
+                owner: "buyer".to_string(),
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.tokens, vec!["user2".to_string()]);
+}
+
+#[test]
+fn test_admin_handover() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+    let _res = instantiate(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("creator", &[]),
+        init_msg(&env),
+    )
+    .unwrap();
+
+    let propose_msg = ProposeNewAdmin {
+        admin: "admin2".to_string(),
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("admin2", &[]),
+        propose_msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("admin1", &[]),
+        CancelAdminProposal {},
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::NoPendingAdmin {});
+
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("admin1", &[]),
+        propose_msg.clone(),
+    )
+    .unwrap();
+    let res: ConfigResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::Config {}).unwrap()).unwrap();
+    assert_eq!(res.admin, "admin1");
+    assert_eq!(res.pending_admin, Some("admin2".to_string()));
+
+    // a cancelled proposal can not be accepted
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("admin1", &[]),
+        CancelAdminProposal {},
+    )
+    .unwrap();
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("admin2", &[]),
+        AcceptAdmin {},
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("admin1", &[]),
+        propose_msg,
+    )
+    .unwrap();
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("admin1", &[]),
+        GrantRole {
+            role: Role::Operator,
+            address: "operator1".to_string(),
+        },
+    )
+    .unwrap();
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("user1", &[]),
+        AcceptAdmin {},
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("admin2", &[]),
+        AcceptAdmin {},
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "accept_admin"),
+            attr("previous_admin", "admin1"),
+            attr("admin", "admin2"),
+        ]
+    );
+
+    // the admin roles move with the handover, roles granted to others stay
+    let res: RolesResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::Roles {}).unwrap()).unwrap();
+    assert_eq!(
+        res,
+        RolesResponse {
+            owner: "admin2".to_string(),
+            operators: vec!["admin2".to_string(), "operator1".to_string()],
+            pausers: vec!["admin2".to_string()],
+        }
+    );
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("admin1", &[]),
+        Pause { reason: None },
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+
+    let team_wallet_msg = UpdateTeamWallet {
+        team_wallet: "team_wallet2".to_string(),
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("admin1", &[]),
+        team_wallet_msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("admin2", &[]),
+        team_wallet_msg,
+    )
+    .unwrap();
+    let res: ConfigResponse =
+        from_binary(&query(deps.as_ref(), env, QueryMsg::Config {}).unwrap()).unwrap();
+    assert_eq!(res.admin, "admin2");
+    assert_eq!(res.pending_admin, None);
+    assert_eq!(res.team_wallet, "team_wallet2");
+}
+
+#[test]
+fn test_roles() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+    let _res = instantiate(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("creator", &[]),
+        init_msg(&env),
+    )
+    .unwrap();
+    let res: RolesResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::Roles {}).unwrap()).unwrap();
+    assert_eq!(
+        res,
+        RolesResponse {
+            owner: "admin1".to_string(),
+            operators: vec!["admin1".to_string()],
+            pausers: vec!["admin1".to_string()],
+        }
+    );
+
+    let grant_msg = GrantRole {
+        role: Role::Operator,
+        address: "uploader".to_string(),
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("uploader", &[]),
+        grant_msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("admin1", &[]),
+        grant_msg,
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "grant_role"),
+            attr("role", "operator"),
+            attr("address", "uploader"),
+        ]
+    );
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("admin1", &[]),
+        RevokeRole {
+            role: Role::Operator,
+            address: "admin1".to_string(),
+        },
+    )
+    .unwrap();
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("admin1", &[]),
+        RevokeRole {
+            role: Role::Operator,
+            address: "admin1".to_string(),
+        },
+    )
+    .unwrap_err();
+    assert_eq!(
+        res,
+        ContractError::MissingRole {
+            address: "admin1".to_string(),
+            role: "operator".to_string(),
+        }
+    );
+
+    let info = mock_info("user1", &[coin(10000, "uusd")]);
+    let deposit_msg = Deposit {
+        cap: None,
+        proof: None,
+    };
+    let _res = execute(deps.as_mut(), env, info, deposit_msg).unwrap();
+
+    // the owner no longer uploads allocations, the operator does
+    let env = env_at(DEPOSIT_PERIOD);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("admin1", &[]),
+        update_msg("user1", 10000, 0),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("uploader", &[]),
+        update_msg("user1", 10000, 0),
+    )
+    .unwrap();
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("uploader", &[]),
+        UpdateTeamWallet {
+            team_wallet: "team_wallet2".to_string(),
+        },
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("uploader", &[]),
+        EnableWithdraw {
+            check_solvency: None,
+        },
+    )
+    .unwrap();
+
+    let res: RolesResponse =
+        from_binary(&query(deps.as_ref(), env, QueryMsg::Roles {}).unwrap()).unwrap();
+    assert_eq!(res.operators, vec!["uploader".to_string()]);
+    assert_eq!(res.pausers, vec!["admin1".to_string()]);
+}
+
+#[test]
+fn test_pause() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+    let _res = instantiate(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("creator", &[]),
+        init_msg(&env),
+    )
+    .unwrap();
+    let info = mock_info("user1", &[coin(10000, "uusd")]);
+    let deposit_msg = Deposit {
+        cap: None,
+        proof: None,
+    };
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        deposit_msg.clone(),
+    )
+    .unwrap();
+
+    let pause_msg = Pause {
+        reason: Some("allocation error".to_string()),
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("user1", &[]),
+        pause_msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("admin1", &[]),
+        pause_msg.clone(),
+    )
+    .unwrap();
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("admin1", &[]),
+        pause_msg,
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::AlreadyPaused {});
+    let res = execute(deps.as_mut(), env.clone(), info, deposit_msg).unwrap_err();
+    assert_eq!(
+        res,
+        ContractError::Paused {
+            reason: Some("allocation error".to_string()),
+        }
+    );
+    let res: PhaseResponse =
+        from_binary(&query(deps.as_ref(), env, QueryMsg::Phase {}).unwrap()).unwrap();
+    assert!(res.paused);
+    assert_eq!(res.pause_reason, Some("allocation error".to_string()));
+
+    // admin paths keep working so the allocation can be fixed
+    let admin = mock_info("admin1", &[]);
+    let env = env_at(DEPOSIT_PERIOD);
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        update_msg("user1", 10000, 0),
+    )
+    .unwrap();
+    let _res = execute(
+        deps.as_mut(),
+        env,
+        admin.clone(),
+        EnableWithdraw {
+            check_solvency: None,
+        },
+    )
+    .unwrap();
+
+    let env = env_at(DEPOSIT_PERIOD + LOCK_TIME + VESTING_TIME);
+    let claim_msg = Claim {
+        investor: None,
+        recipient: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("user1", &[]),
+        claim_msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(
+        res,
+        ContractError::Paused {
+            reason: Some("allocation error".to_string()),
+        }
+    );
+
+    let _res = execute(deps.as_mut(), env.clone(), admin.clone(), Unpause {}).unwrap();
+    let res = execute(deps.as_mut(), env.clone(), admin, Unpause {}).unwrap_err();
+    assert_eq!(res, ContractError::NotPaused {});
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("user1", &[]),
+        claim_msg,
+    )
+    .unwrap();
+    let res: PhaseResponse =
+        from_binary(&query(deps.as_ref(), env, QueryMsg::Phase {}).unwrap()).unwrap();
+    assert!(!res.paused);
+    assert_eq!(res.pause_reason, None);
+}
+
+#[test]
+fn test_update_schedule() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+    let now = env.block.time.seconds();
+    let _res = instantiate(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("creator", &[]),
+        init_msg(&env),
+    )
+    .unwrap();
+    let info = mock_info("user1", &[coin(10000, "uusd")]);
+    let deposit_msg = Deposit {
+        cap: None,
+        proof: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, deposit_msg).unwrap();
+
+    let delay_tge = UpdateSchedule {
+        start_time: Some(Uint128::from(now + DEPOSIT_PERIOD + 1000)),
+        lock_time: None,
+        vesting_time: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("user1", &[]),
+        delay_tge.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+    let admin = mock_info("admin1", &[]);
+    let res = execute(deps.as_mut(), env, admin.clone(), delay_tge).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "update_schedule"),
+            attr("old_start_time", (now + DEPOSIT_PERIOD).to_string()),
+            attr("new_start_time", (now + DEPOSIT_PERIOD + 1000).to_string()),
+            attr("old_lock_time", LOCK_TIME.to_string()),
+            attr("new_lock_time", LOCK_TIME.to_string()),
+            attr("old_vesting_time", VESTING_TIME.to_string()),
+            attr("new_vesting_time", VESTING_TIME.to_string()),
+        ]
+    );
+
+    let env = env_at(DEPOSIT_PERIOD);
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        update_msg("user1", 10000, 0),
+    )
+    .unwrap();
+    let _res = execute(
+        deps.as_mut(),
+        env,
+        admin.clone(),
+        EnableWithdraw {
+            check_solvency: None,
+        },
+    )
+    .unwrap();
+
+    // nothing is vested during the cliff, so it can still be extended
+    let env = env_at(DEPOSIT_PERIOD + LOCK_TIME / 2);
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        UpdateSchedule {
+            start_time: None,
+            lock_time: Some(Uint128::from(LOCK_TIME + 1000)),
+            vesting_time: None,
+        },
+    )
+    .unwrap();
+    let res = execute(
+        deps.as_mut(),
+        env,
+        admin.clone(),
+        UpdateSchedule {
+            start_time: Some(Uint128::from(now + DEPOSIT_PERIOD)),
+            lock_time: None,
+            vesting_time: None,
+        },
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::ScheduleMovedEarlier {});
+
+    let env = env_at(DEPOSIT_PERIOD + 2000 + LOCK_TIME + VESTING_TIME / 2);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        UpdateSchedule {
+            start_time: None,
+            lock_time: None,
+            vesting_time: Some(Uint128::from(VESTING_TIME * 2)),
+        },
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::VestedAmountReduced {});
+
+    let res: ClaimableAmountResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env,
+            QueryMsg::ClaimableAmount {
+                wallet: "user1".to_string(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.token_avaiable_to_claim, "5000");
+}
+
+#[test]
+fn test_migrate() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+    let now = env.block.time.seconds();
+
+    // storage as left by the unversioned contract, deposits still open
+    let legacy_config = LegacyConfig {
+        admin: deps.api.addr_canonicalize("admin1").unwrap(),
+        team_wallet: deps.api.addr_canonicalize("team_wallet").unwrap(),
+        token_addr: deps.api.addr_canonicalize("sayve_token").unwrap(),
+        stable_denom: "uusd".to_string(),
+        start_time: now + DEPOSIT_PERIOD,
+        lock_time: LOCK_TIME,
+        vesting_time: VESTING_TIME,
+    };
+    LEGACY_CONFIG
+        .save(deps.as_mut().storage, &legacy_config)
+        .unwrap();
+    LEGACY_STATUS
+        .save(
+            deps.as_mut().storage,
+            &LegacyStatus {
+                can_deposit: true,
+                can_withdraw: false,
+            },
+        )
+        .unwrap();
+    DEPOSIT
+        .save(
+            deps.as_mut().storage,
+            &Addr::unchecked("user1"),
+            &DepositData {
+                total_deposited: Uint128::from(10000u128),
+                deposit_history: vec![DepositInfo {
+                    date: env.block.time,
+                    amount: Uint128::from(10000u128),
+                }],
+            },
+        )
+        .unwrap();
+
+    let res = migrate(deps.as_mut(), env.clone(), MigrateMsg { legacy: None }).unwrap_err();
+    assert_eq!(res, ContractError::MissingDepositEndTime {});
+    let msg = MigrateMsg {
+        legacy: Some(LegacyMigrateMsg {
+            deposit_start_time: None,
+            deposit_end_time: Uint128::from(now + DEPOSIT_PERIOD),
+        }),
+    };
+    let res = migrate(deps.as_mut(), env.clone(), msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "migrate"),
+            attr("from_version", "legacy"),
+            attr("to_version", env!("CARGO_PKG_VERSION")),
+        ]
+    );
+    let res: PhaseResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::Phase {}).unwrap()).unwrap();
+    assert_eq!(res.phase, Phase::DepositOpen);
+    let res: StatsResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::Stats {}).unwrap()).unwrap();
+    assert_eq!(res.depositors, 1);
+    assert_eq!(res.total_deposited, Uint128::from(10000u128));
+
+    // the migrated sale runs through the current flow
+    let admin = mock_info("admin1", &[]);
+    let env = env_at(DEPOSIT_PERIOD);
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        update_msg("user1", 10000, 0),
+    )
+    .unwrap();
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin,
+        EnableWithdraw {
+            check_solvency: None,
+        },
+    )
+    .unwrap();
+    let res: PhaseResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::Phase {}).unwrap()).unwrap();
+    assert_eq!(res.phase, Phase::Claiming);
+
+    let res = migrate(deps.as_mut(), env.clone(), MigrateMsg { legacy: None }).unwrap();
+    assert_eq!(
+        res.attributes[1],
+        attr("from_version", env!("CARGO_PKG_VERSION"))
+    );
+    cw2::set_contract_version(deps.as_mut().storage, "crates.io:sayve-prefund", "99.0.0").unwrap();
+    let res = migrate(deps.as_mut(), env.clone(), MigrateMsg { legacy: None }).unwrap_err();
+    assert_eq!(
+        res,
+        ContractError::Downgrade {
+            from: "99.0.0".to_string(),
+            to: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    );
+    cw2::set_contract_version(deps.as_mut().storage, "crates.io:other", "1.0.0").unwrap();
+    let res = migrate(deps.as_mut(), env, MigrateMsg { legacy: None }).unwrap_err();
+    assert_eq!(
+        res,
+        ContractError::WrongContract {
+            contract: "crates.io:other".to_string(),
+        }
+    );
+}
+
+// storage as left by the unversioned contract once deposits closed
+fn save_legacy_sale(deps: DepsMut, env: &Env, deposits: &[(&str, u128)]) {
+    let legacy_config = LegacyConfig {
+        admin: deps.api.addr_canonicalize("admin1").unwrap(),
+        team_wallet: deps.api.addr_canonicalize("team_wallet").unwrap(),
+        token_addr: deps.api.addr_canonicalize("sayve_token").unwrap(),
+        stable_denom: "uusd".to_string(),
+        start_time: env.block.time.seconds() + DEPOSIT_PERIOD,
+        lock_time: LOCK_TIME,
+        vesting_time: VESTING_TIME,
+    };
+    LEGACY_CONFIG.save(deps.storage, &legacy_config).unwrap();
+    LEGACY_STATUS
+        .save(
+            deps.storage,
+            &LegacyStatus {
+                can_deposit: false,
+                can_withdraw: false,
+            },
+        )
+        .unwrap();
+    for (user, amount) in deposits {
+        DEPOSIT
+            .save(
+                deps.storage,
+                &Addr::unchecked(*user),
+                &DepositData {
+                    total_deposited: Uint128::from(*amount),
+                    deposit_history: vec![DepositInfo {
+                        date: env.block.time,
+                        amount: Uint128::from(*amount),
+                    }],
+                },
+            )
+            .unwrap();
+    }
+}
+
+#[test]
+fn test_migrate_legacy_sale() {
+    // the old Update already sent user1's and user2's deposits to the team
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+    save_legacy_sale(deps.as_mut(), &env, &[("user1", 10000), ("user2", 10000)]);
+    let user_data = [
+        (
+            "user1",
+            UserData {
+                allocation: Uint128::from(8000u128),
+                refunded: Uint128::from(2000u128),
+            },
+        ),
+        (
+            "user2",
+            UserData {
+                allocation: Uint128::from(10000u128),
+                refunded: Uint128::zero(),
+            },
+        ),
+    ];
+    for (user, data) in user_data.iter() {
+        USER_DATA
+            .save(deps.as_mut().storage, &Addr::unchecked(*user), data)
+            .unwrap();
+    }
+    let _res = migrate(deps.as_mut(), env.clone(), MigrateMsg { legacy: None }).unwrap();
+    let uusd_refunds = |owed: u128| DenomRefunds {
+        denom: Denom::Native("uusd".to_string()),
+        owed: Uint128::from(owed),
+        returned: Uint128::zero(),
+    };
+    assert_eq!(
+        DENOM_REFUNDS.load(deps.as_ref().storage, "uusd").unwrap(),
+        uusd_refunds(2000)
+    );
+
+    // re-submitting the batch sends nothing a second time
+    let admin = mock_info("admin1", &[]);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        Update(vec![
+            UserUpdateData {
+                user_addr: "user1".to_string(),
+                allocation: Uint128::from(8000u128),
+                refunded: Uint128::from(2000u128),
+                schedule: None,
+                clear_schedule: None,
+            },
+            UserUpdateData {
+                user_addr: "user2".to_string(),
+                allocation: Uint128::from(10000u128),
+                refunded: Uint128::zero(),
+                schedule: None,
+                clear_schedule: None,
+            },
+        ]),
+    )
+    .unwrap();
+    assert!(res.messages.is_empty());
+    assert_eq!(res.attributes[1], attr("transfer_amount", "0"));
+    assert_eq!(res.attributes[4], attr("amended_users", "user1,user2"));
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin,
+        update_msg("user1", 9000, 1000),
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: "team_wallet".to_string(),
+            amount: vec![coin(1000, "uusd")],
+        })
+    );
+    assert_eq!(
+        DENOM_REFUNDS.load(deps.as_ref().storage, "uusd").unwrap(),
+        uusd_refunds(1000)
+    );
+
+    // a legacy sale without uploaded allocations closes through Finalize
+    let mut deps = mock_dependencies(&[]);
+    save_legacy_sale(deps.as_mut(), &env, &[("user1", 30000), ("user2", 10000)]);
+    let _res = migrate(deps.as_mut(), env.clone(), MigrateMsg { legacy: None }).unwrap();
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("admin1", &[]),
+        Finalize {
+            token_price: Decimal::percent(50),
+            tokens_for_sale: Uint128::from(40000u128),
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: "team_wallet".to_string(),
+            amount: vec![coin(20000, "uusd")],
+        })
+    );
+    assert_eq!(
+        DENOM_REFUNDS.load(deps.as_ref().storage, "uusd").unwrap(),
+        uusd_refunds(20000)
+    );
+
+    // a legacy sale that never opened keeps the window it is given
+    let mut deps = mock_dependencies(&[]);
+    save_legacy_sale(deps.as_mut(), &env, &[]);
+    let now = env.block.time.seconds();
+    let msg = MigrateMsg {
+        legacy: Some(LegacyMigrateMsg {
+            deposit_start_time: Some(Uint128::from(now + 100)),
+            deposit_end_time: Uint128::from(now + DEPOSIT_PERIOD),
+        }),
+    };
+    let _res = migrate(deps.as_mut(), env.clone(), msg).unwrap();
+    let res: PhaseResponse =
+        from_binary(&query(deps.as_ref(), env, QueryMsg::Phase {}).unwrap()).unwrap();
+    assert_eq!(res.phase, Phase::NotStarted);
+    assert_eq!(res.time_to_next_phase, Some(100));
+}
+
+#[test]
+fn test_list() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+    let _res = instantiate(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("creator", &[]),
+        init_msg(&env),
+    )
+    .unwrap();
+    let deposit_msg = Deposit {
+        cap: None,
+        proof: None,
+    };
+    for user in ["user1", "user2", "user3"] {
+        let info = mock_info(user, &[coin(10000, "uusd")]);
+        let _res = execute(deps.as_mut(), env.clone(), info, deposit_msg.clone()).unwrap();
+    }
+    let admin = mock_info("admin1", &[]);
+    let env = env_at(DEPOSIT_PERIOD);
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        update_msg("user2", 8000, 2000),
+    )
+    .unwrap();
+    let _res = execute(
+        deps.as_mut(),
+        env,
+        admin,
+        EnableWithdraw {
+            check_solvency: None,
+        },
+    )
+    .unwrap();
+    let env = env_at(DEPOSIT_PERIOD + LOCK_TIME + VESTING_TIME / 2);
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("user2", &[]),
+        Claim {
+            investor: None,
+            recipient: None,
+        },
+    )
+    .unwrap();
+
+    let list = |start_after: Option<&str>, order: Option<OrderBy>| -> Vec<String> {
+        let res: ListResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::List {
+                    start_after: start_after.map(|s| s.to_string()),
+                    limit: Some(2),
+                    order,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        res.investors.into_iter().map(|i| i.wallet).collect()
+    };
+    assert_eq!(list(None, None), vec!["user1", "user2"]);
+    assert_eq!(list(Some("user2"), None), vec!["user3"]);
+    assert_eq!(list(None, Some(OrderBy::Desc)), vec!["user3", "user2"]);
+    assert_eq!(list(Some("user2"), Some(OrderBy::Desc)), vec!["user1"]);
+
+    let res: ListResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env,
+            QueryMsg::List {
+                start_after: Some("user1".to_string()),
+                limit: Some(1),
+                order: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.investors,
+        vec![WalletInfo {
+            wallet: "user2".to_string(),
+            total: Uint128::from(10000u128),
+            allocation: Uint128::from(8000u128),
+            refunded: Uint128::from(2000u128),
+            released: Uint128::from(4000u128),
+        }]
+    );
+}
+
+#[test]
+fn test_stats() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+    let _res = instantiate(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("creator", &[]),
+        init_msg(&env),
+    )
+    .unwrap();
+    let deposit_msg = Deposit {
+        cap: None,
+        proof: None,
+    };
+    for user in ["user1", "user2", "user1"] {
+        let info = mock_info(user, &[coin(5000, "uusd")]);
+        let _res = execute(deps.as_mut(), env.clone(), info, deposit_msg.clone()).unwrap();
+    }
+    let admin = mock_info("admin1", &[]);
+    let env = env_at(DEPOSIT_PERIOD);
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        update_msg("user1", 8000, 2000),
+    )
+    .unwrap();
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        update_msg("user2", 5000, 0),
+    )
+    .unwrap();
+    // an amendment replaces the user's share of every total
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        update_msg("user1", 9000, 1000),
+    )
+    .unwrap();
+    let _res = execute(
+        deps.as_mut(),
+        env,
+        admin,
+        EnableWithdraw {
+            check_solvency: None,
+        },
+    )
+    .unwrap();
+
+    let env = env_at(DEPOSIT_PERIOD + LOCK_TIME + VESTING_TIME / 2);
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("user2", &[]),
+        Claim {
+            investor: None,
+            recipient: None,
+        },
+    )
+    .unwrap();
+    let res: StatsResponse =
+        from_binary(&query(deps.as_ref(), env, QueryMsg::Stats {}).unwrap()).unwrap();
+    assert_eq!(
+        res,
+        StatsResponse {
+            total_deposited: Uint128::from(15000u128),
+            depositors: 2,
+            total_allocated: Uint128::from(14000u128),
+            total_refunded: Uint128::from(1000u128),
+            total_returned: Uint128::zero(),
+            total_released: Uint128::from(2500u128),
+            total_swept: Uint128::from(14000u128),
+        }
+    );
+}
+
+#[test]
+fn test_solvency() {
+    let mut deps = mock_dependencies_with_querier(&[coin(3000, "uusd"), coin(500, "uluna")]);
+    deps.querier
+        .with_token_balance("sayve_token", MOCK_CONTRACT_ADDR, Uint128::zero());
+    let env = mock_env();
+    let _res = instantiate(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("creator", &[]),
+        init_msg(&env),
+    )
+    .unwrap();
+    let admin = mock_info("admin1", &[]);
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        UpdateAcceptedDenom {
+            denom: Denom::Native("uluna".to_string()),
+            price_source: Some(PriceSource::Fixed {
+                rate: Decimal::from_ratio(2u128, 1u128),
+            }),
+        },
+    )
+    .unwrap();
+    let deposit_msg = Deposit {
+        cap: None,
+        proof: None,
+    };
+    let info = mock_info("user1", &[coin(10000, "uusd")]);
+    let _res = execute(deps.as_mut(), env.clone(), info, deposit_msg.clone()).unwrap();
+    let info = mock_info("user2", &[coin(5000, "uluna")]);
+    let _res = execute(deps.as_mut(), env, info, deposit_msg).unwrap();
+
+    // each refund is owed in the denom the wallet deposited
+    let env = env_at(DEPOSIT_PERIOD);
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        admin.clone(),
+        Update(vec![
+            UserUpdateData {
+                user_addr: "user1".to_string(),
+                allocation: Uint128::from(8000u128),
+                refunded: Uint128::from(2000u128),
+                schedule: None,
+                clear_schedule: None,
+            },
+            UserUpdateData {
+                user_addr: "user2".to_string(),
+                allocation: Uint128::from(8000u128),
+                refunded: Uint128::from(2000u128),
+                schedule: None,
+                clear_schedule: None,
+            },
+        ]),
+    )
+    .unwrap();
+    let res: SolvencyResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::Solvency {}).unwrap()).unwrap();
+    assert_eq!(
+        res,
+        SolvencyResponse {
+            token_balance: Uint128::zero(),
+            token_liability: Uint128::from(16000u128),
+            token_surplus: Uint128::zero(),
+            token_shortfall: Uint128::from(16000u128),
+            denoms: vec![
+                DenomSolvency {
+                    denom: Denom::Native("uusd".to_string()),
+                    balance: Uint128::from(3000u128),
+                    refund_liability: Uint128::from(2000u128),
+                    surplus: Uint128::from(1000u128),
+                    shortfall: Uint128::zero(),
+                },
+                DenomSolvency {
+                    denom: Denom::Native("uluna".to_string()),
+                    balance: Uint128::from(500u128),
+                    refund_liability: Uint128::from(1000u128),
+                    surplus: Uint128::zero(),
+                    shortfall: Uint128::from(500u128),
+                },
+            ],
+        }
+    );
+
+    let check_msg = EnableWithdraw {
+        check_solvency: Some(true),
+    };
+    let res = execute(deps.as_mut(), env.clone(), admin.clone(), check_msg.clone());
+    assert_eq!(
+        res.unwrap_err(),
+        ContractError::Insolvent {
+            token_shortfall: Uint128::from(16000u128),
+            denom_shortfall: vec![(Denom::Native("uluna".to_string()), Uint128::from(500u128))],
+        }
+    );
+
+    // the stable surplus does not cover the luna refunds
+    deps.querier
+        .with_token_balance("sayve_token", MOCK_CONTRACT_ADDR, Uint128::from(16500u128));
+    let res = execute(deps.as_mut(), env.clone(), admin.clone(), check_msg.clone());
+    assert_eq!(
+        res.unwrap_err(),
+        ContractError::Insolvent {
+            token_shortfall: Uint128::zero(),
+            denom_shortfall: vec![(Denom::Native("uluna".to_string()), Uint128::from(500u128))],
+        }
+    );
+
+    deps.querier.with_balance(
+        MOCK_CONTRACT_ADDR,
+        vec![coin(3000, "uusd"), coin(1000, "uluna")],
+    );
+    let res: SolvencyResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::Solvency {}).unwrap()).unwrap();
+    assert_eq!(res.token_surplus, Uint128::from(500u128));
+    assert_eq!(res.denoms[1].shortfall, Uint128::zero());
+    let _res = execute(deps.as_mut(), env.clone(), admin, check_msg).unwrap();
+
+    // a paid refund leaves the totals, only the unpaid uusd refund is still owed
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("user2", &[]),
+        Claim {
+            investor: None,
+            recipient: None,
+        },
+    )
+    .unwrap();
+    let res: SolvencyResponse =
+        from_binary(&query(deps.as_ref(), env, QueryMsg::Solvency {}).unwrap()).unwrap();
+    assert_eq!(res.denoms[0].refund_liability, Uint128::from(2000u128));
+    assert_eq!(res.denoms[1].refund_liability, Uint128::zero());
 }
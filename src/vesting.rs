@@ -0,0 +1,187 @@
+use cosmwasm_std::Uint128;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::error::ContractError;
+
+pub const BPS_DENOMINATOR: u64 = 10000;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Checkpoint {
+    pub time: u64,
+    // share of the allocation unlocked at `time`, in basis points
+    pub bps: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduleType {
+    // per-second release over vesting_time once the cliff is over
+    #[default]
+    Linear,
+    // `count` equal unlocks, one every `interval` seconds once the cliff is over
+    Periodic {
+        interval: u64,
+        count: u64,
+    },
+    // fixed unlocks at absolute times; with the TGE share they must add up to 100%
+    Custom {
+        checkpoints: Vec<Checkpoint>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct VestingSchedule {
+    // TGE, tge_unlock_bps of the allocation unlocks here
+    pub start_time: u64,
+    // cliff after TGE during which nothing more unlocks
+    pub lock_time: u64,
+    // linear release of the rest once the cliff is over
+    pub vesting_time: u64,
+    pub tge_unlock_bps: u64,
+    pub schedule_type: ScheduleType,
+}
+
+impl VestingSchedule {
+    pub fn validate(&self) -> Result<(), ContractError> {
+        if self.tge_unlock_bps > BPS_DENOMINATOR {
+            return Err(ContractError::TgeUnlockTooHigh {});
+        }
+        match &self.schedule_type {
+            ScheduleType::Linear => {}
+            ScheduleType::Periodic { interval, count } => {
+                if *interval == 0 || *count == 0 {
+                    return Err(ContractError::InvalidPeriodicSchedule {});
+                }
+            }
+            ScheduleType::Custom { checkpoints } => {
+                if checkpoints.windows(2).any(|w| w[0].time >= w[1].time) {
+                    return Err(ContractError::UnorderedCheckpoints {});
+                }
+                let total_bps = checkpoints
+                    .iter()
+                    .fold(self.tge_unlock_bps, |acc, c| acc.saturating_add(c.bps));
+                if total_bps != BPS_DENOMINATOR {
+                    return Err(ContractError::IncompleteCheckpoints { total_bps });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn cliff_end_time(&self) -> u64 {
+        self.start_time + self.lock_time
+    }
+
+    pub fn end_time(&self) -> u64 {
+        match &self.schedule_type {
+            ScheduleType::Linear => self.cliff_end_time() + self.vesting_time,
+            ScheduleType::Periodic { interval, count } => self.cliff_end_time() + interval * count,
+            ScheduleType::Custom { checkpoints } => checkpoints
+                .last()
+                .map_or(self.start_time, |c| std::cmp::max(c.time, self.start_time)),
+        }
+    }
+
+    /// Part of `allocation` unlocked at `current_time`: nothing before TGE, the TGE share
+    /// until the cliff ends, then the rest as the schedule type releases it.
+    pub fn vested_amount(&self, allocation: Uint128, current_time: u64) -> Uint128 {
+        let tge_amount = if current_time < self.start_time {
+            Uint128::zero()
+        } else {
+            allocation.multiply_ratio(self.tge_unlock_bps, BPS_DENOMINATOR)
+        };
+        match &self.schedule_type {
+            // rounded once over the TGE and checkpoint shares, and whole after the last one
+            ScheduleType::Custom { .. } if current_time >= self.end_time() => allocation,
+            ScheduleType::Custom { checkpoints } => {
+                let tge_bps = if current_time < self.start_time {
+                    0
+                } else {
+                    self.tge_unlock_bps
+                };
+                let unlocked_bps: u64 = checkpoints
+                    .iter()
+                    .filter(|c| c.time <= current_time)
+                    .map(|c| c.bps)
+                    .sum();
+                allocation.multiply_ratio(tge_bps + unlocked_bps, BPS_DENOMINATOR)
+            }
+            _ if current_time < self.cliff_end_time() => tge_amount,
+            ScheduleType::Linear => {
+                let passed_time = current_time - self.cliff_end_time();
+                if passed_time >= self.vesting_time {
+                    allocation
+                } else {
+                    tge_amount
+                        + (allocation - tge_amount).multiply_ratio(passed_time, self.vesting_time)
+                }
+            }
+            ScheduleType::Periodic { interval, count } => {
+                let periods = (current_time - self.cliff_end_time()) / interval;
+                if periods >= *count {
+                    allocation
+                } else {
+                    tge_amount + (allocation - tge_amount).multiply_ratio(periods, *count)
+                }
+            }
+        }
+    }
+
+    /// Time and size of the next increase of the vested amount after `current_time`,
+    /// None once everything is vested.
+    pub fn next_unlock(&self, allocation: Uint128, current_time: u64) -> Option<(u64, Uint128)> {
+        let vested_amount = self.vested_amount(allocation, current_time);
+        if vested_amount >= allocation {
+            return None;
+        }
+        let mut candidates = vec![];
+        if current_time < self.start_time && self.tge_unlock_bps > 0 {
+            candidates.push(self.start_time);
+        }
+        let cliff_end_time = self.cliff_end_time();
+        match &self.schedule_type {
+            ScheduleType::Linear => {
+                let rest =
+                    allocation - allocation.multiply_ratio(self.tge_unlock_bps, BPS_DENOMINATOR);
+                if rest.is_zero() {
+                    // everything unlocks at TGE
+                } else if self.vesting_time == 0 {
+                    candidates.push(cliff_end_time);
+                } else {
+                    // first second at which one more unit of the linear part is released
+                    let linear_vested = if current_time < cliff_end_time {
+                        Uint128::zero()
+                    } else {
+                        vested_amount - (allocation - rest)
+                    };
+                    let target = linear_vested + Uint128::from(1u128);
+                    let mut passed_time =
+                        target.multiply_ratio(self.vesting_time, rest).u128() as u64;
+                    if rest.multiply_ratio(passed_time, self.vesting_time) < target {
+                        passed_time += 1;
+                    }
+                    candidates.push(cliff_end_time + passed_time);
+                }
+            }
+            ScheduleType::Periodic { interval, .. } => {
+                let periods = if current_time < cliff_end_time {
+                    0
+                } else {
+                    (current_time - cliff_end_time) / interval
+                };
+                candidates.push(cliff_end_time + (periods + 1) * interval);
+            }
+            ScheduleType::Custom { checkpoints } => {
+                if let Some(c) = checkpoints.iter().find(|c| c.time > current_time) {
+                    candidates.push(c.time);
+                }
+            }
+        }
+        let next_time = candidates.into_iter().min()?;
+        Some((
+            next_time,
+            self.vested_amount(allocation, next_time) - vested_amount,
+        ))
+    }
+}